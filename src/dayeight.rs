@@ -1,6 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use itertools::Itertools;
+use regex::Regex;
 
 pub fn solve_part_one(input: &str) -> u32 {
     let (seed, memory) = parse_input(input);
@@ -24,66 +25,281 @@ fn parse_input(input: &str) -> (String, BTreeMap<String, (String, String)>) {
         let right = right_raw[..right_raw.len() - 1].to_string();
         mapping.insert(key.to_string(), (left, right));
     }
-    return (seed.trim().to_string(), mapping);
+    (seed.trim().to_string(), mapping)
 }
 
-fn simulate(instructions: &str, memory: BTreeMap<String, (String, String)>) -> u64 {
-    let mut current_pos = 0;
-    let mut acc = 0;
-    let instructions: Vec<String> = instructions.chars().map(|c| c.to_string()).collect();
-    let num_instructions = instructions.len();
-    let mut current_node = "AAA".to_string();
-    while current_node != *"ZZZ" {
-        let instruction = &instructions[current_pos % num_instructions];
-        println!("{}: {}: {}", current_pos, instruction, current_node);
-        let next_node = if instruction == "L" {
-            memory.get(&current_node).unwrap().0.clone()
+/// The map with node names interned into ids, so walking it is just indexing.
+pub struct Network {
+    /// 0 for `L` and 1 for `R`.
+    instructions: Vec<usize>,
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    next: Vec<[usize; 2]>,
+}
+
+/// Where the walk from a single start ends up. After `tail` steps the walker
+/// is on a (node, instruction index) state it returns to every `period` steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Walk {
+    pub tail: u64,
+    pub period: u64,
+    /// Every step count below `tail + period` that lands on a goal node.
+    pub goal_steps: Vec<u64>,
+}
+
+impl Walk {
+    pub fn is_goal_at(&self, step: u64) -> bool {
+        let step = if step < self.tail {
+            step
         } else {
-            memory.get(&current_node).unwrap().1.clone()
+            self.tail + (step - self.tail) % self.period
         };
-        current_node = next_node;
-        current_pos += 1;
-        acc += 1;
+        self.goal_steps.binary_search(&step).is_ok()
     }
-    acc
 }
 
-fn simulate_two(instructions: &str, memory: BTreeMap<String, (String, String)>) -> u64 {
-    let instructions: Vec<String> = instructions.chars().map(|c| c.to_string()).collect();
-    let num_instructions = instructions.len();
-    let current_nodes = memory
-        .keys()
-        .filter(|k| k.ends_with('A')).cloned()
-        .collect_vec();
-    let mut num_steps = vec![];
-    for node in current_nodes.iter() {
-        let mut current_pos = 0;
-        let mut current_node = node.clone();
-        let mut acc = 0;
-        while !current_node.ends_with('Z') {
-            let instruction = &instructions[current_pos % num_instructions];
-            println!("{}: {}: {}", current_pos, instruction, current_node);
-            let next_node = if instruction == "L" {
-                memory.get(&current_node).unwrap().0.clone()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalkError {
+    /// There are no walkers at all.
+    NoStart,
+    /// The walkers are never all on goal nodes at the same time.
+    NoSimultaneousArrival,
+}
+
+impl std::fmt::Display for WalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalkError::NoStart => write!(f, "no start nodes"),
+            WalkError::NoSimultaneousArrival => {
+                write!(f, "the walkers never reach goal nodes at the same time")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WalkError {}
+
+impl Network {
+    pub fn new(instructions: &str, memory: &BTreeMap<String, (String, String)>) -> Self {
+        let names = memory.keys().cloned().collect_vec();
+        let ids: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect();
+        let next = names
+            .iter()
+            .map(|name| {
+                let (left, right) = &memory[name];
+                [ids[left], ids[right]]
+            })
+            .collect_vec();
+        let instructions = instructions
+            .chars()
+            .map(|c| if c == 'L' { 0 } else { 1 })
+            .collect_vec();
+        Network {
+            instructions,
+            names,
+            ids,
+            next,
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = usize> {
+        0..self.names.len()
+    }
+
+    /// Follows the instructions from `start`, calling `visit` with every step
+    /// and node until a (node, instruction index) state repeats.
+    /// Returns the tail and period of the walk.
+    fn trace(&self, start: usize, mut visit: impl FnMut(u64, usize)) -> (u64, u64) {
+        let num_instructions = self.instructions.len();
+        let mut first_seen = vec![u64::MAX; self.names.len() * num_instructions];
+        let mut node = start;
+        let mut step = 0;
+        loop {
+            let state = node * num_instructions + step as usize % num_instructions;
+            if first_seen[state] != u64::MAX {
+                return (first_seen[state], step - first_seen[state]);
+            }
+            first_seen[state] = step;
+            visit(step, node);
+            node = self.next[node][self.instructions[step as usize % num_instructions]];
+            step += 1;
+        }
+    }
+
+    /// Follows the instructions from `start`, recording each (node, instruction index)
+    /// state until one repeats.
+    pub fn walk(&self, start: usize, is_goal: impl Fn(usize) -> bool) -> Walk {
+        let mut goal_steps = vec![];
+        let (tail, period) = self.trace(start, |step, node| {
+            if is_goal(node) {
+                goal_steps.push(step);
+            }
+        });
+        Walk {
+            tail,
+            period,
+            goal_steps,
+        }
+    }
+
+    pub fn select(&self, selector: &Selector) -> Vec<usize> {
+        self.nodes()
+            .filter(|&node| selector.matches(self.name(node)))
+            .collect_vec()
+    }
+
+    /// Every goal node the walk from `start` ever reaches, with the number
+    /// of steps it takes to get there the first time.
+    pub fn reachable_goals(&self, start: &str, goals: &Selector) -> BTreeMap<String, u64> {
+        let mut reached = BTreeMap::new();
+        if let Some(start) = self.id(start) {
+            self.trace(start, |step, node| {
+                if goals.matches(self.name(node)) {
+                    reached.entry(self.name(node).to_string()).or_insert(step);
+                }
+            });
+        }
+        reached
+    }
+
+    /// The first step at which a walker from every start node is on a goal node.
+    pub fn simultaneous(&self, starts: &Selector, goals: &Selector) -> Result<u64, WalkError> {
+        let walks = self
+            .select(starts)
+            .into_iter()
+            .map(|start| self.walk(start, |node| goals.matches(self.name(node))))
+            .collect_vec();
+        first_simultaneous(&walks)
+    }
+}
+
+/// Picks out start or goal nodes by name.
+#[derive(Debug, Clone)]
+pub enum Selector {
+    Names(BTreeSet<String>),
+    Suffix(String),
+    Pattern(Regex),
+}
+
+impl Selector {
+    pub fn names<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Selector::Names(names.into_iter().map(|n| n.to_string()).collect())
+    }
+
+    pub fn suffix(suffix: &str) -> Self {
+        Selector::Suffix(suffix.to_string())
+    }
+
+    pub fn pattern(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Selector::Pattern)
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::Names(names) => names.contains(name),
+            Selector::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            Selector::Pattern(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// The first step at which every walk is on a goal node.
+///
+/// Before the longest tail is over some walker may still be on a one-off
+/// visit, so those steps are checked directly. After that every walker is
+/// looping, and each choice of goal offsets per walker is a system of
+/// congruences solved with the generalised Chinese remainder theorem.
+pub fn first_simultaneous(walks: &[Walk]) -> Result<u64, WalkError> {
+    if walks.is_empty() {
+        return Err(WalkError::NoStart);
+    }
+    let longest_tail = walks.iter().map(|w| w.tail).max().unwrap();
+    if let Some(step) = (0..longest_tail).find(|&step| walks.iter().all(|w| w.is_goal_at(step))) {
+        return Ok(step);
+    }
+
+    walks
+        .iter()
+        .map(|walk| {
+            walk.goal_steps
+                .iter()
+                .filter(|&&step| step >= walk.tail)
+                .map(|&step| (step as i128, walk.period as i128))
+                .collect_vec()
+        })
+        .multi_cartesian_product()
+        .filter_map(|congruences| {
+            let (residue, modulus) = congruences.into_iter().try_fold((0, 1), crt)?;
+            // lift the solution past every tail
+            let tail = longest_tail as i128;
+            let step = if residue >= tail {
+                residue
             } else {
-                memory.get(&current_node).unwrap().1.clone()
+                residue + (tail - residue + modulus - 1) / modulus * modulus
             };
-            current_node = next_node;
-            current_pos += 1;
-            acc += 1;
-        }
-        num_steps.push(acc);
+            Some(step as u64)
+        })
+        .min()
+        .ok_or(WalkError::NoSimultaneousArrival)
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
-    // compute the lcm of the numbers in num_steps
-    let mut lcm = num_steps[0];
-    for i in 1..num_steps.len() {
-        lcm = num::integer::lcm(lcm, num_steps[i]);
+}
+
+/// Combines `x = a1 (mod m1)` and `x = a2 (mod m2)` into a single congruence,
+/// or None if they contradict each other. Moduli need not be coprime.
+fn crt((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let (g, p, _) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
     }
-    lcm
+    let lcm = m1 / g * m2;
+    let k = ((a2 - a1) / g * p).rem_euclid(m2 / g);
+    Some(((a1 + m1 * k).rem_euclid(lcm), lcm))
+}
+
+fn simulate(instructions: &str, memory: BTreeMap<String, (String, String)>) -> u64 {
+    let network = Network::new(instructions, &memory);
+    network
+        .simultaneous(&Selector::names(["AAA"]), &Selector::names(["ZZZ"]))
+        .unwrap()
+}
+
+fn simulate_two(instructions: &str, memory: BTreeMap<String, (String, String)>) -> u64 {
+    let network = Network::new(instructions, &memory);
+    network
+        .simultaneous(&Selector::suffix("A"), &Selector::suffix("Z"))
+        .unwrap()
+}
+
+/// Builds the network straight from the puzzle input.
+pub fn parse_network(input: &str) -> Network {
+    let (instructions, memory) = parse_input(input);
+    Network::new(&instructions, &memory)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn solves_8_1_easy() {
         let input = std::fs::read_to_string("input/8_easy.txt").unwrap();
@@ -108,6 +324,45 @@ mod tests {
         assert_eq!(super::solve_part_two(&input), 6);
     }
 
+    #[test]
+    fn combines_walks_without_shared_period() {
+        let walk = |tail, period, goal_steps: Vec<u64>| Walk {
+            tail,
+            period,
+            goal_steps,
+        };
+        // one ghost reaches a goal only on the way in
+        let walks = [walk(2, 3, vec![1, 3]), walk(0, 2, vec![1])];
+        assert_eq!(first_simultaneous(&walks), Ok(1));
+        // goals at 3 (mod 4) and 1 (mod 6): steps 7, 19, ...
+        let walks = [walk(0, 4, vec![3]), walk(0, 6, vec![1])];
+        assert_eq!(first_simultaneous(&walks), Ok(7));
+        // odd and even steps never line up
+        let walks = [walk(0, 2, vec![1]), walk(0, 4, vec![2])];
+        assert_eq!(
+            first_simultaneous(&walks),
+            Err(WalkError::NoSimultaneousArrival)
+        );
+    }
+
+    #[test]
+    fn answers_queries_with_any_selector() {
+        let input = std::fs::read_to_string("input/8_easy2.txt").unwrap();
+        let network = parse_network(&input);
+        let goals = network.reachable_goals("22A", &Selector::suffix("Z"));
+        assert_eq!(goals, BTreeMap::from([("22Z".to_string(), 3)]));
+        let starts = Selector::pattern("^1.A$").unwrap();
+        let goals = Selector::names(["11Z"]);
+        assert_eq!(network.simultaneous(&starts, &goals), Ok(2));
+        assert!(network
+            .reachable_goals("11A", &Selector::suffix("Q"))
+            .is_empty());
+        assert_eq!(
+            network.simultaneous(&Selector::suffix("Q"), &goals),
+            Err(WalkError::NoStart)
+        );
+    }
+
     #[test]
     fn solves_8_2_hard() {
         let input = std::fs::read_to_string("input/8_real.txt").unwrap();
@@ -1,24 +1,8 @@
-pub fn solve(input: &str, expansion: usize) -> usize {
-    let mut grid: Vec<Vec<char>> = input.lines().map(|line| line.chars().collect()).collect();
-    let mut galaxies = Vec::new();
-    for (row, line) in grid.iter().enumerate() {
-        for (col, &cell) in line.iter().enumerate() {
-            if cell == '#' {
-                galaxies.push((row, col));
-            }
-        }
-    }
+use itertools::Itertools;
 
-    expand(&mut grid, &mut galaxies, expansion);
-    let mut total = 0;
-    for i in 0..galaxies.len() {
-        for j in i + 1..galaxies.len() {
-            let (r1, c1) = galaxies[i];
-            let (r2, c2) = galaxies[j];
-            total += (r1 as i64 - r2 as i64).abs() + (c1 as i64 - c2 as i64).abs();
-        }
-    }
-    total as usize
+pub fn solve(input: &str, expansion: usize) -> usize {
+    let map = GalaxyMap::parse(input, expansion as u64, expansion as u64);
+    map.pairwise_sum() as usize
 }
 
 pub fn solve_part_one(input: &str) -> usize {
@@ -29,44 +13,205 @@ pub fn solve_part_two(input: &str) -> usize {
     solve(input, 1000000)
 }
 
-fn expand(grid: &mut Vec<Vec<char>>, galaxies: &mut Vec<(usize, usize)>, expansion: usize) {
-    let length = grid.len();
-    let width = grid[0].len();
-
-    let mut no_galaxy_row = Vec::new();
-    let mut no_galaxy_col = Vec::new();
+/// Galaxy positions after expansion, with each axis kept sorted alongside
+/// running sums so that distance totals don't need to visit every pair.
+pub struct GalaxyMap {
+    galaxies: Vec<(u64, u64)>,
+    /// Galaxy indices ordered by row, then by column.
+    by_row: Vec<usize>,
+    by_col: Vec<usize>,
+    /// `row_prefix[k]` is the sum of the first `k` rows in `by_row` order.
+    row_prefix: Vec<u64>,
+    col_prefix: Vec<u64>,
+}
 
-    for i in 0..length {
-        if !grid[i].contains(&'#') {
-            no_galaxy_row.push(i);
+impl GalaxyMap {
+    /// Reads the image, growing every empty row `row_expansion` times
+    /// and every empty column `col_expansion` times. Lines shorter than the
+    /// widest one count as empty past their end.
+    pub fn parse(input: &str, row_expansion: u64, col_expansion: u64) -> Self {
+        let grid = input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.trim().chars().collect_vec())
+            .collect_vec();
+        let mut raw = Vec::new();
+        let mut row_used = vec![false; grid.len()];
+        let mut col_used = vec![false; grid.iter().map(|line| line.len()).max().unwrap_or(0)];
+        for (row, line) in grid.iter().enumerate() {
+            for (col, &cell) in line.iter().enumerate() {
+                if cell == '#' {
+                    raw.push((row, col));
+                    row_used[row] = true;
+                    col_used[col] = true;
+                }
+            }
         }
+
+        let row_at = expanded_coordinates(&row_used, row_expansion);
+        let col_at = expanded_coordinates(&col_used, col_expansion);
+        let galaxies = raw
+            .into_iter()
+            .map(|(row, col)| (row_at[row], col_at[col]))
+            .collect_vec();
+        GalaxyMap::new(galaxies)
     }
 
-    for i in 0..width {
-        let to_check: Vec<char> = grid.iter().map(|row| row[i]).collect();
-        if !to_check.contains(&'#') {
-            no_galaxy_col.push(i);
+    pub fn new(galaxies: Vec<(u64, u64)>) -> Self {
+        let by_row = (0..galaxies.len())
+            .sorted_by_key(|&i| galaxies[i])
+            .collect_vec();
+        let by_col = (0..galaxies.len())
+            .sorted_by_key(|&i| (galaxies[i].1, galaxies[i].0))
+            .collect_vec();
+        let prefix = |order: &[usize], axis: fn(&(u64, u64)) -> u64| {
+            let mut sums = vec![0];
+            for &i in order {
+                sums.push(sums.last().unwrap() + axis(&galaxies[i]));
+            }
+            sums
+        };
+        let row_prefix = prefix(&by_row, |g| g.0);
+        let col_prefix = prefix(&by_col, |g| g.1);
+        GalaxyMap {
+            galaxies,
+            by_row,
+            by_col,
+            row_prefix,
+            col_prefix,
         }
     }
 
-    for r in no_galaxy_row.into_iter().rev() {
-        for g in &mut *galaxies {
-            if g.0 > r {
-                g.0 += expansion - 1
+    pub fn galaxies(&self) -> &[(u64, u64)] {
+        &self.galaxies
+    }
+
+    /// The sum of the distances between every pair of galaxies.
+    /// Along one sorted axis the `k`th coordinate is subtracted from each of the
+    /// `k` before it, so the total is `sum(k * x_k) - sum(prefix_k)`.
+    pub fn pairwise_sum(&self) -> u64 {
+        let axis_sum = |order: &[usize], prefix: &[u64], axis: fn(&(u64, u64)) -> u64| {
+            order
+                .iter()
+                .enumerate()
+                .map(|(k, &i)| k as u64 * axis(&self.galaxies[i]) - prefix[k])
+                .sum::<u64>()
+        };
+        axis_sum(&self.by_row, &self.row_prefix, |g| g.0)
+            + axis_sum(&self.by_col, &self.col_prefix, |g| g.1)
+    }
+
+    /// The sum of the distances from galaxy `index` to every other galaxy.
+    pub fn distance_sum(&self, index: usize) -> u64 {
+        let (row, col) = self.galaxies[index];
+        let axis_sum =
+            |order: &[usize], prefix: &[u64], value: u64, axis: fn(&(u64, u64)) -> u64| {
+                let below = order.partition_point(|&i| axis(&self.galaxies[i]) < value);
+                let total = *prefix.last().unwrap();
+                let n = order.len() as u64;
+                (value * below as u64 - prefix[below])
+                    + (total - prefix[below] - value * (n - below as u64))
+            };
+        axis_sum(&self.by_row, &self.row_prefix, row, |g| g.0)
+            + axis_sum(&self.by_col, &self.col_prefix, col, |g| g.1)
+    }
+
+    fn distance(&self, a: usize, b: usize) -> u64 {
+        let (r1, c1) = self.galaxies[a];
+        let (r2, c2) = self.galaxies[b];
+        r1.abs_diff(r2) + c1.abs_diff(c2)
+    }
+
+    /// The closest other galaxy and its distance. Galaxies are checked
+    /// outwards from `index` in row order, stopping once the row gap alone
+    /// is larger than the best distance found.
+    pub fn nearest(&self, index: usize) -> Option<(usize, u64)> {
+        let position = self.by_row.iter().position(|&i| i == index)?;
+        let row = self.galaxies[index].0;
+        let mut best: Option<(usize, u64)> = None;
+        let up = self.by_row[..position].iter().rev();
+        let down = self.by_row[position + 1..].iter();
+        for side in [up.copied().collect_vec(), down.copied().collect_vec()] {
+            for other in side {
+                if best.is_some_and(|(_, d)| self.galaxies[other].0.abs_diff(row) > d) {
+                    break;
+                }
+                let distance = self.distance(index, other);
+                if best.is_none_or(|(_, d)| distance < d) {
+                    best = Some((other, distance));
+                }
             }
         }
+        best
     }
-    for c in no_galaxy_col.into_iter().rev() {
-        for g in &mut *galaxies {
-            if g.1 > c {
-                g.1 += expansion - 1
-            }
+
+    /// The galaxy furthest away from `index` and its distance.
+    /// Manhattan distance is the largest of the distances along the
+    /// `row + col` and `row - col` diagonals, so only their extremes matter.
+    pub fn farthest(&self, index: usize) -> Option<(usize, u64)> {
+        let diagonals = |i: usize| {
+            let (row, col) = self.galaxies[i];
+            [row as i64 + col as i64, row as i64 - col as i64]
+        };
+        let mut candidates = vec![];
+        for axis in 0..2 {
+            let others = (0..self.galaxies.len()).filter(|&i| i != index);
+            candidates.extend(others.clone().min_by_key(|&i| diagonals(i)[axis]));
+            candidates.extend(others.max_by_key(|&i| diagonals(i)[axis]));
         }
+        candidates
+            .into_iter()
+            .map(|other| (other, self.distance(index, other)))
+            .max_by_key(|&(_, distance)| distance)
     }
 }
 
+/// Where each original row (or column) ends up once the empty ones grow.
+fn expanded_coordinates(used: &[bool], expansion: u64) -> Vec<u64> {
+    let mut coordinates = Vec::with_capacity(used.len());
+    let mut next = 0;
+    for &is_used in used {
+        coordinates.push(next);
+        next += if is_used { 1 } else { expansion };
+    }
+    coordinates
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_distance_queries() {
+        let input = std::fs::read_to_string("input/11_easy.txt").unwrap();
+        let map = GalaxyMap::parse(&input, 2, 2);
+        let n = map.galaxies().len();
+        for i in 0..n {
+            let distances = (0..n).filter(|&j| j != i).map(|j| (j, map.distance(i, j)));
+            let nearest = distances.clone().map(|(_, d)| d).min();
+            let farthest = distances.clone().map(|(_, d)| d).max();
+            assert_eq!(map.nearest(i).map(|(_, d)| d), nearest);
+            assert_eq!(map.farthest(i).map(|(_, d)| d), farthest);
+            assert_eq!(map.distance_sum(i), distances.map(|(_, d)| d).sum());
+        }
+        // galaxies 5 and 9 from the puzzle are 9 apart
+        assert_eq!(map.distance(4, 8), 9);
+    }
+
+    #[test]
+    fn expands_rows_and_columns_separately() {
+        let input = "#..\n...\n..#";
+        assert_eq!(
+            GalaxyMap::parse(input, 10, 1).galaxies(),
+            &[(0, 0), (11, 2)]
+        );
+        assert_eq!(GalaxyMap::parse(input, 1, 10).pairwise_sum(), 2 + 11);
+        assert_eq!(
+            GalaxyMap::parse("#\n..#", 10, 10).galaxies(),
+            &[(0, 0), (1, 11)]
+        );
+    }
+
     #[test]
     fn solves_11_1_easy() {
         let input = std::fs::read_to_string("input/11_easy.txt").unwrap();
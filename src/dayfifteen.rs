@@ -1,75 +1,311 @@
-use itertools::Itertools;
-
 pub fn solve_part_one(input: &str) -> u32 {
     parse(input).iter().map(|s| hash_function(s)).sum()
 }
 
 pub fn solve_part_two(input: &str) -> usize {
-    let groups: Vec<&str> = input.split(',').collect();
-
-    let mut items: Vec<Vec<(String, u32)>> = vec![vec![]; 256];
-
-    groups.iter().for_each(|it| {
-        let name = if it.ends_with('-') {
-            &it[..it.len() - 1]
-        } else if it.chars().nth_back(1).unwrap() == '=' {
-            &it[..it.len() - 2]
-        } else {
-            return;
-        };
-
-        let hash_value = hash_function(name);
-
-        if it.ends_with('-') {
-            items[hash_value as usize] = items[hash_value as usize]
-                .clone()
-                .into_iter()
-                .filter(|(n, _)| n != name)
-                .collect();
-        } else if it.chars().nth_back(1).unwrap() == '=' {
-            let length = it.chars().last().unwrap().to_digit(10).unwrap();
-            let item = items[hash_value as usize]
-                .clone()
-                .into_iter()
-                .map(|(n, v)| {
-                    if n == name {
-                        (n.clone(), length)
-                    } else {
-                        (n.clone(), v)
-                    }
-                })
-                .collect_vec();
-
-            if items[hash_value as usize].iter().any(|(n, _)| n == name) {
-                items[hash_value as usize] = item;
-            } else {
-                items[hash_value as usize].push((name.to_string(), length));
+    let mut boxes = LensMap::new();
+    for step in parse(input) {
+        boxes.apply(parse_step(step).unwrap());
+    }
+    boxes.focusing_power()
+}
+
+/// The non-empty boxes right after a step, numbered from zero like the puzzle does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub step: String,
+    pub boxes: Vec<(usize, Vec<(String, u32)>)>,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "After \"{}\":", self.step)?;
+        for (number, lenses) in &self.boxes {
+            write!(f, "Box {}:", number)?;
+            for (label, focal_length) in lenses {
+                write!(f, " [{} {}]", label, focal_length)?;
             }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the initialization sequence one step at a time, yielding the state after
+/// each step. Stops after the first step that doesn't parse.
+pub fn replay(input: &str) -> impl Iterator<Item = Result<Frame, StepError>> + '_ {
+    let mut boxes = LensMap::new();
+    let mut failed = false;
+    parse(input).into_iter().map_while(move |step| {
+        if failed {
+            return None;
         }
-    });
+        let parsed = parse_step(step);
+        failed = parsed.is_err();
+        Some(parsed.map(|parsed| {
+            boxes.apply(parsed);
+            Frame {
+                step: step.to_string(),
+                boxes: boxes
+                    .buckets()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, lenses)| !lenses.is_empty())
+                    .map(|(number, lenses)| (number, lenses.clone()))
+                    .collect(),
+            }
+        }))
+    })
+}
 
-    items
-        .iter()
-        .enumerate()
-        .flat_map(|(i, box_item)| {
-            box_item
-                .iter()
-                .enumerate()
-                .map(move |(j, (_, v))| (i + 1) * (j + 1) * (*v as usize))
-        })
-        .sum()
+/// Every frame of the replay in the puzzle's format, separated by blank lines.
+pub fn render(input: &str) -> Result<String, StepError> {
+    let frames = replay(input)
+        .map(|frame| frame.map(|frame| frame.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(frames.join("\n"))
 }
 
 fn parse(input: &str) -> Vec<&str> {
-    input.split(',').collect()
+    input
+        .split(',')
+        .map(|step| step.trim())
+        .filter(|step| !step.is_empty())
+        .collect()
 }
 
-fn hash_function(s: &str) -> u32 {
+pub fn hash_function(s: &str) -> u32 {
     s.chars().fold(0, |h, c| ((h + c as u32) * 17) % 256)
 }
 
+/// A hash map that keeps every bucket in insertion order, like the boxes of
+/// lenses in the puzzle. Replacing a value keeps its place in the bucket.
+#[derive(Debug, Clone)]
+pub struct LensMap<K, V, H = fn(&K) -> usize> {
+    buckets: Vec<Vec<(K, V)>>,
+    hasher: H,
+}
+
+impl<V> LensMap<String, V> {
+    /// 256 buckets picked by the puzzle's HASH algorithm.
+    pub fn new() -> Self {
+        LensMap::with_hasher(256, |label: &String| hash_function(label) as usize)
+    }
+}
+
+impl<V> Default for LensMap<String, V> {
+    fn default() -> Self {
+        LensMap::new()
+    }
+}
+
+impl<K: PartialEq, V, H: Fn(&K) -> usize> LensMap<K, V, H> {
+    /// A map with `buckets` buckets; a key goes in bucket `hasher(key) % buckets`.
+    pub fn with_hasher(buckets: usize, hasher: H) -> Self {
+        assert!(buckets > 0, "a LensMap needs at least one bucket");
+        LensMap {
+            buckets: (0..buckets).map(|_| Vec::new()).collect(),
+            hasher,
+        }
+    }
+
+    fn bucket_of(&self, key: &K) -> usize {
+        (self.hasher)(key) % self.buckets.len()
+    }
+
+    /// Adds `value` at the end of the key's bucket, or replaces the value in
+    /// place if the key is already there, returning the old value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let bucket = self.bucket_of(&key);
+        let entries = &mut self.buckets[bucket];
+        match entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            None => {
+                entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Takes the key out of its bucket, moving the entries behind it forward.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let bucket = self.bucket_of(key);
+        let entries = &mut self.buckets[bucket];
+        let position = entries.iter().position(|(k, _)| k == key)?;
+        Some(entries.remove(position).1)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.buckets[self.bucket_of(key)]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(|bucket| bucket.is_empty())
+    }
+
+    pub fn buckets(&self) -> &[Vec<(K, V)>] {
+        &self.buckets
+    }
+}
+
+impl<K: PartialEq, H: Fn(&K) -> usize> LensMap<K, u32, H> {
+    /// The focusing power of every lens in box order: the product of its box
+    /// number, slot number and focal length, all counted from one.
+    pub fn focusing_powers(&self) -> Vec<(&K, usize)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, bucket)| {
+                bucket
+                    .iter()
+                    .enumerate()
+                    .map(move |(j, (k, v))| (k, (i + 1) * (j + 1) * (*v as usize)))
+            })
+            .collect()
+    }
+
+    pub fn focusing_power(&self) -> usize {
+        self.focusing_powers().iter().map(|(_, power)| power).sum()
+    }
+}
+
+impl<H: Fn(&String) -> usize> LensMap<String, u32, H> {
+    pub fn apply(&mut self, step: Step) {
+        match step {
+            Step::Remove(label) => {
+                self.remove(&label);
+            }
+            Step::Insert(label, focal_length) => {
+                self.insert(label, focal_length);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Remove(String),
+    Insert(String, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepError {
+    /// The step has no `-` or `=` operation.
+    MissingOperation(String),
+    EmptyLabel(String),
+    InvalidFocalLength(String),
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepError::MissingOperation(step) => write!(f, "step `{}` has no operation", step),
+            StepError::EmptyLabel(step) => write!(f, "step `{}` has no label", step),
+            StepError::InvalidFocalLength(step) => {
+                write!(f, "step `{}` has an invalid focal length", step)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+pub fn parse_step(step: &str) -> Result<Step, StepError> {
+    let (label, parsed) = if let Some(label) = step.strip_suffix('-') {
+        (label, Step::Remove(label.to_string()))
+    } else if let Some((label, focal_length)) = step.split_once('=') {
+        let focal_length = focal_length
+            .parse::<u32>()
+            .map_err(|_| StepError::InvalidFocalLength(step.to_string()))?;
+        (label, Step::Insert(label.to_string(), focal_length))
+    } else {
+        return Err(StepError::MissingOperation(step.to_string()));
+    };
+    if label.is_empty() {
+        return Err(StepError::EmptyLabel(step.to_string()));
+    }
+    Ok(parsed)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_steps() {
+        assert_eq!(parse_step("rn=12"), Ok(Step::Insert("rn".to_string(), 12)));
+        assert_eq!(parse_step("cm-"), Ok(Step::Remove("cm".to_string())));
+        assert_eq!(
+            parse_step("qp"),
+            Err(StepError::MissingOperation("qp".to_string()))
+        );
+        assert_eq!(
+            parse_step("qp=x"),
+            Err(StepError::InvalidFocalLength("qp=x".to_string()))
+        );
+        assert_eq!(
+            parse_step("=3"),
+            Err(StepError::EmptyLabel("=3".to_string()))
+        );
+    }
+
+    #[test]
+    fn replays_the_worked_example() {
+        let input = std::fs::read_to_string("input/15_easy.txt").unwrap();
+        let rendered = render(&input).unwrap();
+        assert!(rendered.starts_with(
+            "After \"rn=1\":\nBox 0: [rn 1]\n\nAfter \"cm-\":\nBox 0: [rn 1]\n\n\
+             After \"qp=3\":\nBox 0: [rn 1]\nBox 1: [qp 3]\n\n"
+        ));
+        assert!(rendered
+            .ends_with("After \"ot=7\":\nBox 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n"));
+        assert_eq!(replay(&input).count(), 11);
+
+        let mut boxes = LensMap::new();
+        for step in parse(&input) {
+            boxes.apply(parse_step(step).unwrap());
+        }
+        let powers = boxes
+            .focusing_powers()
+            .into_iter()
+            .map(|(label, power)| (label.as_str(), power))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            powers,
+            vec![("rn", 1), ("cm", 4), ("ot", 28), ("ab", 40), ("pc", 72)]
+        );
+    }
+
+    #[test]
+    fn replay_stops_at_invalid_step() {
+        let frames = replay("rn=1,cm,qp=3").collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[1],
+            Err(StepError::MissingOperation("cm".to_string()))
+        );
+        assert!(render("rn=1,cm,qp=3").is_err());
+    }
+
+    #[test]
+    fn lens_map_with_custom_hasher() {
+        let mut map = LensMap::with_hasher(2, |key: &u32| *key as usize);
+        assert_eq!(map.insert(1, "a"), None);
+        assert_eq!(map.insert(3, "b"), None);
+        assert_eq!(map.insert(1, "c"), Some("a"));
+        assert_eq!(map.buckets()[1], vec![(1, "c"), (3, "b")]);
+        assert_eq!(map.remove(&1), Some("c"));
+        assert_eq!(map.get(&3), Some(&"b"));
+        assert_eq!(map.len(), 1);
+    }
+
     #[test]
     fn solves_15_1_easy() {
         let input = std::fs::read_to_string("input/15_easy.txt").unwrap();
@@ -1,135 +1,431 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::str::FromStr;
+
 use itertools::Itertools;
-/// Algorithm:
-/// 1. Parse input into state and blocks
-/// 2. for each block
-///     1. check if the item in the state is in the source range
-///     2. if it is, replace it with the destination range
-///     3.  if it isnot then use the item in the state
-///     4. this is the new state
-/// return the lowest value in the state
-pub fn solve_part_one(input: &str) -> u64 {
-    let (mut state, blocks) = parse_input(input);
-    for block in blocks {
-        state = state
+
+/// A piecewise shift of `u64` values, as described by one block of the almanac.
+/// Values inside a source range move by the same amount as its start does;
+/// everything else maps to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RangeMap {
+    /// `(source, destination)` pairs sorted by source, with disjoint sources.
+    entries: Vec<(Range<u64>, Range<u64>)>,
+}
+
+impl RangeMap {
+    /// Builds the map from `(source, destination)` pairs, which must not share
+    /// any source values.
+    pub fn new(entries: Vec<(Range<u64>, Range<u64>)>) -> Result<Self, AlmanacError> {
+        let entries = entries
+            .into_iter()
+            .filter(|(source, _)| !source.is_empty())
+            .sorted_by_key(|(source, _)| source.start)
+            .collect_vec();
+        if let Some(((first, _), (second, _))) = entries
             .iter()
-            .map(|item| {
-                let mut item = *item;
-                for (source_range, destination_range) in block.iter() {
-                    if source_range.contains(&item) {
-                        item = destination_range.start + (item - source_range.start);
-                        break;
-                    }
+            .tuple_windows()
+            .find(|((a, _), (b, _))| a.end > b.start)
+        {
+            return Err(AlmanacError::OverlappingEntries(
+                first.clone(),
+                second.clone(),
+            ));
+        }
+        Ok(RangeMap { entries })
+    }
+
+    pub fn entries(&self) -> &[(Range<u64>, Range<u64>)] {
+        &self.entries
+    }
+
+    /// Splits `0..u64::MAX` into ranges that each move by a single offset,
+    /// filling the gaps between entries with identity pieces.
+    fn segments(&self) -> Vec<(Range<u64>, i128)> {
+        let mut segments = vec![];
+        let mut cursor = 0;
+        for (source, destination) in &self.entries {
+            if cursor < source.start {
+                segments.push((cursor..source.start, 0));
+            }
+            let offset = destination.start as i128 - source.start as i128;
+            segments.push((source.clone(), offset));
+            cursor = source.end;
+        }
+        if cursor < u64::MAX {
+            segments.push((cursor..u64::MAX, 0));
+        }
+        segments
+    }
+
+    fn from_segments(segments: impl IntoIterator<Item = (Range<u64>, i128)>) -> Self {
+        let mut merged: Vec<(Range<u64>, i128)> = vec![];
+        for (range, offset) in segments.into_iter().sorted_by_key(|(r, _)| r.start) {
+            match merged.last_mut() {
+                Some((last, last_offset)) if last.end == range.start && *last_offset == offset => {
+                    last.end = range.end;
                 }
-                item
+                _ => merged.push((range, offset)),
+            }
+        }
+        RangeMap::new(
+            merged
+                .into_iter()
+                .filter(|&(_, offset)| offset != 0)
+                .map(|(range, offset)| (shift(&range, 0), shift(&range, offset)))
+                .collect_vec(),
+        )
+        .expect("merged segments are disjoint")
+    }
+
+    pub fn get(&self, value: u64) -> u64 {
+        let index = self
+            .entries
+            .partition_point(|(source, _)| source.start <= value);
+        match index.checked_sub(1).map(|i| &self.entries[i]) {
+            Some((source, destination)) if source.contains(&value) => {
+                destination.start + (value - source.start)
+            }
+            _ => value,
+        }
+    }
+
+    /// The ranges that `range` maps onto, in the order of their sources.
+    pub fn image(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        self.segments()
+            .into_iter()
+            .filter_map(|(segment, offset)| {
+                let start = segment.start.max(range.start);
+                let end = segment.end.min(range.end);
+                (start < end).then(|| shift(&(start..end), offset))
             })
+            .collect_vec()
+    }
+
+    /// The map going back from destinations to sources, or None if two
+    /// values map onto the same one.
+    pub fn inverse(&self) -> Option<RangeMap> {
+        let images = self
+            .segments()
+            .into_iter()
+            .map(|(range, offset)| (shift(&range, offset), -offset))
+            .sorted_by_key(|(range, _)| range.start)
             .collect_vec();
+        let overlapping = images
+            .iter()
+            .tuple_windows()
+            .any(|((a, _), (b, _))| a.end > b.start);
+        if overlapping {
+            return None;
+        }
+        Some(RangeMap::from_segments(images))
+    }
+
+    /// The map applying `self` first and then `next`.
+    pub fn compose(&self, next: &RangeMap) -> RangeMap {
+        let next_segments = next.segments();
+        let pieces = self.segments().into_iter().flat_map(|(range, offset)| {
+            let image = shift(&range, offset);
+            next_segments
+                .iter()
+                .filter_map(move |(segment, next_offset)| {
+                    let start = segment.start.max(image.start);
+                    let end = segment.end.min(image.end);
+                    (start < end).then(|| (shift(&(start..end), -offset), offset + next_offset))
+                })
+        });
+        RangeMap::from_segments(pieces.collect_vec())
+    }
+
+    /// The lowest value `range` maps to. Every piece of the map is increasing,
+    /// so only the start of `range` and the breakpoints inside it need checking.
+    pub fn min_over(&self, range: Range<u64>) -> Option<u64> {
+        self.image(range).iter().map(|r| r.start).min()
     }
-    *state.iter().min().unwrap()
+}
+
+/// Moves `range` by `offset`. Offsets are `i128` so that they can span the
+/// whole `u64` range either way; the result must still fit in a `u64`.
+fn shift(range: &Range<u64>, offset: i128) -> Range<u64> {
+    let moved = |value: u64| u64::try_from(value as i128 + offset).unwrap();
+    moved(range.start)..moved(range.end)
+}
+
+pub fn solve_part_one(input: &str) -> u64 {
+    let almanac = Almanac::from_str(input).unwrap();
+    let seed_to_location = almanac.convert("seed", "location").unwrap();
+    almanac
+        .seeds
+        .iter()
+        .map(|&seed| seed_to_location.get(seed))
+        .min()
+        .unwrap()
 }
 
 pub fn solve_part_two(input: &str) -> u64 {
-    let (raw_state, blocks) = parse_input(input);
-    let mut state = raw_state
+    let almanac = Almanac::from_str(input).unwrap();
+    let seed_to_location = almanac.convert("seed", "location").unwrap();
+    almanac
+        .seeds
         .iter()
-        .step_by(2)
-        .zip(raw_state[1..].iter().step_by(2))
-        .map(|(a, b)| {
-            let range_end = a + b;
-            *a..range_end
-        })
-        .collect_vec();
-    println!("State: {:?}", state);
-    for block in blocks {
-        state = state
+        .tuples()
+        .filter_map(|(&start, &length)| seed_to_location.min_over(start..start + length))
+        .min()
+        .unwrap()
+}
+
+/// One `x-to-y map:` block of the almanac.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub from: String,
+    pub to: String,
+    pub map: RangeMap,
+}
+
+/// The seeds and every conversion between categories, in whatever order
+/// the blocks were listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Almanac {
+    pub seeds: Vec<u64>,
+    pub conversions: Vec<Conversion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlmanacError {
+    /// The first section is not a `seeds:` line.
+    MissingSeeds,
+    /// A block does not start with an `x-to-y map:` header.
+    MalformedHeader(String),
+    /// A mapping line is not three numbers.
+    MalformedEntry(String),
+    /// No conversion starts or ends in this category.
+    UnknownCategory(String),
+    /// There is no chain of conversions between the two categories.
+    MissingLink { from: String, to: String },
+    /// The conversions loop back onto themselves through these categories.
+    Cycle(Vec<String>),
+    /// Two entries of one map share source values.
+    OverlappingEntries(Range<u64>, Range<u64>),
+}
+
+impl std::fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlmanacError::MissingSeeds => write!(f, "the almanac does not list any seeds"),
+            AlmanacError::MalformedHeader(header) => write!(f, "malformed map header `{}`", header),
+            AlmanacError::MalformedEntry(line) => write!(f, "malformed map entry `{}`", line),
+            AlmanacError::UnknownCategory(name) => write!(f, "unknown category `{}`", name),
+            AlmanacError::MissingLink { from, to } => {
+                write!(f, "no chain of maps converts {} to {}", from, to)
+            }
+            AlmanacError::Cycle(categories) => {
+                write!(f, "maps form a cycle: {}", categories.join(" -> "))
+            }
+            AlmanacError::OverlappingEntries(first, second) => {
+                write!(f, "map sources {:?} and {:?} overlap", first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AlmanacError {}
+
+impl FromStr for Almanac {
+    type Err = AlmanacError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let groups = s.split("\n\n").collect_vec();
+        //seeds: 79 14 55 13
+        let seeds = groups[0]
+            .trim()
+            .strip_prefix("seeds:")
+            .ok_or(AlmanacError::MissingSeeds)?
+            .split_whitespace()
+            .map(|x| x.parse::<u64>().map_err(|_| AlmanacError::MissingSeeds))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut conversions = vec![];
+        for block in groups[1..].iter().filter(|b| !b.trim().is_empty()) {
+            let lines = block.trim().lines().map(|line| line.trim()).collect_vec();
+            let (from, to) = lines[0]
+                .strip_suffix(" map:")
+                .and_then(|name| name.split_once("-to-"))
+                .ok_or_else(|| AlmanacError::MalformedHeader(lines[0].to_string()))?;
+            conversions.push(Conversion {
+                from: from.to_string(),
+                to: to.to_string(),
+                map: parse_block(lines)?,
+            });
+        }
+
+        let almanac = Almanac { seeds, conversions };
+        almanac.check_acyclic()?;
+        Ok(almanac)
+    }
+}
+
+impl Almanac {
+    fn outgoing(&self, category: &str) -> impl Iterator<Item = &Conversion> {
+        let category = category.to_string();
+        self.conversions.iter().filter(move |c| c.from == category)
+    }
+
+    fn has_category(&self, category: &str) -> bool {
+        self.conversions
             .iter()
-            .flat_map(|item| {
-                let mut mapped_ranges = Vec::new();
-                let mut unmapped_ranges = vec![item.clone()];
-                for (source_range, destination_range) in block.iter() {
-                    // Temporary vector to store the ranges that are not mapped in the current iteration
-                    let mut temp_unmapped = Vec::new();
-                    // Iterate over each unmapped range
-                    for item in unmapped_ranges {
-                        // Calculate the range before the source range
-                        let before_source = item.start..item.end.min(source_range.start);
-                        // Calculate the range within the source range
-                        let within_source =
-                            item.start.max(source_range.start)..(source_range.end).min(item.end);
-                        // Calculate the range after the source range
-                        let after_source = (source_range.end).max(item.start)..item.end;
-                        // If the end of the before_source range is greater than the start, add it to the temp_unmapped vector
-                        if before_source.end > before_source.start {
-                            temp_unmapped.push(before_source);
-                        }
-                        // If the end of the within_source range is greater than the start, calculate the corresponding destination range and add it to the mapped_ranges vector
-                        if within_source.end > within_source.start {
-                            mapped_ranges.push(
-                                within_source.start - source_range.start + destination_range.start
-                                    ..within_source.end - source_range.start
-                                        + destination_range.start,
-                            );
-                        }
-                        // If the end of the after_source range is greater than the start, add it to the temp_unmapped vector
-                        if after_source.end > after_source.start {
-                            temp_unmapped.push(after_source);
-                        }
-                    }
-                    // Replace the unmapped_ranges vector with the temp_unmapped vector for the next iteration
-                    unmapped_ranges = temp_unmapped;
+            .any(|c| c.from == category || c.to == category)
+    }
+
+    fn check_acyclic(&self) -> Result<(), AlmanacError> {
+        // depth first search, keeping the categories on the current path
+        fn visit<'a>(
+            almanac: &'a Almanac,
+            category: &'a str,
+            path: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> Result<(), AlmanacError> {
+            if let Some(position) = path.iter().position(|&c| c == category) {
+                let mut cycle = path[position..].iter().map(|c| c.to_string()).collect_vec();
+                cycle.push(category.to_string());
+                return Err(AlmanacError::Cycle(cycle));
+            }
+            if !done.insert(category) {
+                return Ok(());
+            }
+            path.push(category);
+            for conversion in almanac.outgoing(category) {
+                visit(almanac, &conversion.to, path, done)?;
+            }
+            path.pop();
+            Ok(())
+        }
+
+        let mut done = HashSet::new();
+        for conversion in &self.conversions {
+            visit(self, &conversion.from, &mut vec![], &mut done)?;
+        }
+        Ok(())
+    }
+
+    /// The conversions leading from `from` to `to`, fewest first.
+    pub fn path(&self, from: &str, to: &str) -> Result<Vec<&Conversion>, AlmanacError> {
+        for category in [from, to] {
+            if !self.has_category(category) {
+                return Err(AlmanacError::UnknownCategory(category.to_string()));
+            }
+        }
+
+        let mut previous: HashMap<&str, &Conversion> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                let mut path = vec![];
+                let mut current = to;
+                while current != from {
+                    let conversion = previous[current];
+                    path.push(conversion);
+                    current = &conversion.from;
                 }
-                // Extend the mapped_ranges vector with the remaining unmapped ranges
-                mapped_ranges.extend(unmapped_ranges);
-                // Return the mapped_ranges vector
-                mapped_ranges
-            })
+                path.reverse();
+                return Ok(path);
+            }
+            for conversion in self.outgoing(category) {
+                if conversion.to != from && !previous.contains_key(conversion.to.as_str()) {
+                    previous.insert(&conversion.to, conversion);
+                    queue.push_back(&conversion.to);
+                }
+            }
+        }
+        Err(AlmanacError::MissingLink {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    /// A single map converting values of category `from` into category `to`.
+    pub fn convert(&self, from: &str, to: &str) -> Result<RangeMap, AlmanacError> {
+        let maps = self
+            .path(from, to)?
+            .into_iter()
+            .map(|conversion| conversion.map.clone())
             .collect_vec();
-        println!("State: {:?}", state);
+        Ok(compose_all(&maps))
+    }
+
+    /// The categories `value` passes through on its way from `from` to `to`,
+    /// along with its value in each of them.
+    pub fn trace(
+        &self,
+        from: &str,
+        to: &str,
+        value: u64,
+    ) -> Result<Vec<(String, u64)>, AlmanacError> {
+        let path = self.path(from, to)?;
+        let maps = path.iter().map(|c| c.map.clone()).collect_vec();
+        let categories = std::iter::once(from).chain(path.iter().map(|c| c.to.as_str()));
+        Ok(categories
+            .map(|c| c.to_string())
+            .zip(trace_seed(&maps, value))
+            .collect_vec())
+    }
+}
+
+/// Collapses a chain of maps into a single one.
+pub fn compose_all(blocks: &[RangeMap]) -> RangeMap {
+    blocks
+        .iter()
+        .fold(RangeMap::default(), |acc, block| acc.compose(block))
+}
+
+/// The value of `seed` in every category along the chain, the seed itself included.
+pub fn trace_seed(blocks: &[RangeMap], seed: u64) -> Vec<u64> {
+    let mut values = vec![seed];
+    for block in blocks {
+        values.push(block.get(*values.last().unwrap()));
     }
-    println!("Final State: {:?}", state);
-    state.sort_by(|a, b| a.start.cmp(&b.start));
-    state[0].start
+    values
 }
 
-fn parse_block(input: Vec<&str>) -> Vec<(std::ops::Range<u64>, std::ops::Range<u64>)> {
+pub fn parse_block(input: Vec<&str>) -> Result<RangeMap, AlmanacError> {
     // Each block looks like the following:
     //seed-to-soil map:
     // 50 98 2
     // 52 50 48
-    //println!("Parsing block: {:?}", input[0]);
     let input = input[1..].to_vec();
     let mut ranges = Vec::new();
     for line in input {
-        let line = line.split_whitespace().collect_vec();
-        //println!("Parsing line: {:?}", line);
-        let destination_start = line[0].parse::<u64>().unwrap();
-        let source_start = line[1].parse::<u64>().unwrap();
-        let range_capacity = line[2].parse::<u64>().unwrap();
-        let source_range = source_start..(source_start + range_capacity);
-        let destination_range = destination_start..(destination_start + range_capacity);
-        ranges.push((source_range, destination_range));
-    }
-    //println!("Parsed block: {:?}", ranges);
-    ranges
-}
-
-fn parse_input(
-    input: &str,
-) -> (
-    Vec<u64>,
-    Vec<Vec<(std::ops::Range<u64>, std::ops::Range<u64>)>>,
-) {
-    let groups = input.split("\n\n").collect::<Vec<_>>();
-    //seeds: 79 14 55 13
-    let initial_state = groups[0].split(':').collect::<Vec<_>>()[1];
-    let initial_state = initial_state
-        .split_whitespace()
-        .map(|x| x.parse::<u64>().unwrap())
-        .collect::<Vec<_>>();
-    let mut blocks = Vec::new();
-    for block in groups[1..].to_vec() {
-        blocks.push(parse_block(block.split('\n').collect::<Vec<_>>()));
-    }
-    (initial_state, blocks)
+        if line.trim().is_empty() {
+            continue;
+        }
+        let malformed = || AlmanacError::MalformedEntry(line.trim().to_string());
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<u64>().map_err(|_| malformed()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [destination_start, source_start, range_capacity] = numbers[..] else {
+            return Err(malformed());
+        };
+        let source_end = source_start
+            .checked_add(range_capacity)
+            .ok_or_else(malformed)?;
+        let destination_end = destination_start
+            .checked_add(range_capacity)
+            .ok_or_else(malformed)?;
+        ranges.push((source_start..source_end, destination_start..destination_end));
+    }
+    RangeMap::new(ranges)
+}
+
+/// The seeds and the maps from seed to location, in conversion order.
+pub fn parse_input(input: &str) -> (Vec<u64>, Vec<RangeMap>) {
+    let almanac = Almanac::from_str(input).unwrap();
+    let maps = almanac
+        .path("seed", "location")
+        .unwrap()
+        .into_iter()
+        .map(|conversion| conversion.map.clone())
+        .collect_vec();
+    (almanac.seeds, maps)
 }
 
 #[cfg(test)]
@@ -142,14 +438,14 @@ mod tests {
         0 11 42
         42 0 7
         57 7 4";
-        let parsed_block = super::parse_block(raw_block.split("\n").collect::<Vec<_>>());
+        let parsed_block = super::parse_block(raw_block.split("\n").collect::<Vec<_>>()).unwrap();
         assert_eq!(
-            parsed_block,
+            parsed_block.entries(),
             vec![
-                (53..61, 49..57),
-                (11..53, 0..42),
                 (0..7, 42..49),
-                (7..11, 57..61)
+                (7..11, 57..61),
+                (11..53, 0..42),
+                (53..61, 49..57)
             ]
         );
     }
@@ -159,8 +455,102 @@ mod tests {
         let raw_block = "seed-to-soil map:
 50 98 2
 52 50 48";
-        let parsed_block = super::parse_block(raw_block.split("\n").collect::<Vec<_>>());
-        assert_eq!(parsed_block, vec![(98..100, 50..52), (50..98, 52..100),]);
+        let parsed_block = super::parse_block(raw_block.split("\n").collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            parsed_block.entries(),
+            vec![(50..98, 52..100), (98..100, 50..52)]
+        );
+    }
+
+    #[test]
+    fn composes_and_inverts_maps() {
+        let input = std::fs::read_to_string("input/5_easy.txt").unwrap();
+        let (seeds, blocks) = super::parse_input(&input);
+        let composed = super::compose_all(&blocks);
+        let inverse = composed.inverse().unwrap();
+        for seed in 0..120 {
+            let trace = super::trace_seed(&blocks, seed);
+            assert_eq!(composed.get(seed), *trace.last().unwrap());
+            assert_eq!(inverse.get(composed.get(seed)), seed);
+        }
+        assert_eq!(
+            super::trace_seed(&blocks, seeds[0]),
+            vec![79, 81, 81, 81, 74, 78, 78, 82]
+        );
+        assert_eq!(blocks[0].image(45..100), vec![45..50, 52..100, 50..52]);
+        // 10..15 is reached both from 0..5 and from itself
+        let folded = super::RangeMap::new(vec![(0..5, 10..15)]).unwrap();
+        assert_eq!(folded.inverse(), None);
+        assert_eq!(
+            super::RangeMap::new(vec![(0..5, 10..15), (3..8, 10..15)]),
+            Err(super::AlmanacError::OverlappingEntries(0..5, 3..8))
+        );
+    }
+
+    #[test]
+    fn converts_between_any_categories_out_of_order() {
+        let input = std::fs::read_to_string("input/5_easy.txt").unwrap();
+        let mut blocks = input.split("\n\n").collect::<Vec<_>>();
+        blocks[1..].reverse();
+        let almanac: super::Almanac = blocks.join("\n\n").parse().unwrap();
+        assert_eq!(almanac.convert("soil", "humidity").unwrap().get(81), 78);
+        assert_eq!(
+            almanac.trace("seed", "location", 79).unwrap().last(),
+            Some(&("location".to_string(), 82))
+        );
+        assert_eq!(
+            almanac.path("location", "seed").err(),
+            Some(super::AlmanacError::MissingLink {
+                from: "location".to_string(),
+                to: "seed".to_string()
+            })
+        );
+        assert_eq!(
+            almanac.path("seed", "dirt").err(),
+            Some(super::AlmanacError::UnknownCategory("dirt".to_string()))
+        );
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let input = "seeds: 1\n\nseed-to-soil map:\n1 2 3\n\nsoil-to-seed map:\n1 2 3";
+        assert_eq!(
+            input.parse::<super::Almanac>(),
+            Err(super::AlmanacError::Cycle(vec![
+                "seed".to_string(),
+                "soil".to_string(),
+                "seed".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn handles_values_past_i64() {
+        let high = 1 << 63;
+        let map = super::RangeMap::new(vec![
+            (0..5, high + 10..high + 15),
+            (high + 10..high + 15, 0..5),
+        ])
+        .unwrap();
+        assert_eq!(map.get(2), high + 12);
+        assert_eq!(map.get(high + 12), 2);
+        assert_eq!(map.inverse(), Some(map.clone()));
+        assert_eq!(map.compose(&map).get(3), 3);
+        assert_eq!(map.min_over(0..10), Some(5));
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let malformed = |entry: &str| {
+            let input = format!("seeds: 1\n\nseed-to-soil map:\n{}", entry);
+            input.parse::<super::Almanac>().err()
+        };
+        for entry in ["1 2", "1 2 x", "1 2 3 4", "1 18446744073709551615 3"] {
+            assert_eq!(
+                malformed(entry),
+                Some(super::AlmanacError::MalformedEntry(entry.to_string()))
+            );
+        }
     }
 
     #[test]
@@ -1,94 +1,136 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::{collections::HashSet, str::FromStr};
 
 use itertools::Itertools;
 
 pub fn solve_part_one(input: &str) -> u32 {
-    let parsed_input = parse_input(input);
-    // the input has two parts, first the lottery winning numbers and second the numbers we have
-    // we need to count the numbers that we have that are in the winning numbers
-    let overlapping_number_counts = parsed_input
+    parse_input(input).iter().map(Card::points).sum()
+}
+
+pub fn solve_part_two(input: &str) -> u64 {
+    cascade(&parse_input(input))
         .iter()
-        .map(|numbers| {
-            let winning_numbers: HashSet<u32> = HashSet::from_iter(numbers[0].iter().cloned());
-            let my_numbers: HashSet<u32> = HashSet::from_iter(numbers[1].iter().cloned());
-            let overlapping_numbers = winning_numbers.intersection(&my_numbers).collect_vec();
-            let count = overlapping_numbers.len();
-            // println!(
-            //     "winning: {:?} my: {:?} overlap: {:?}",
-            //     winning_numbers, my_numbers, overlapping_numbers
-            // );
-            // return 2 ** count
-            if count == 0 {
-                return 0;
-            }
-            u32::pow(2, (count - 1) as u32)
+        .map(|copies| copies.count)
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    pub id: u32,
+    pub winning: Vec<u32>,
+    pub have: Vec<u32>,
+}
+
+impl FromStr for Card {
+    type Err = ();
+
+    // each line looks like Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, numbers) = s.split_once(':').ok_or(())?;
+        let id = id
+            .strip_prefix("Card")
+            .ok_or(())?
+            .trim()
+            .parse()
+            .map_err(|_| ())?;
+        let (winning, have) = numbers.split_once('|').ok_or(())?;
+        let parse_numbers = |numbers: &str| {
+            numbers
+                .split_whitespace()
+                .map(|n| n.parse::<u32>().map_err(|_| ()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Card {
+            id,
+            winning: parse_numbers(winning)?,
+            have: parse_numbers(have)?,
         })
-        .collect_vec();
-    // println!(
-    //     "overlapping number count is {:?}",
-    //     overlapping_number_counts
-    // );
-    overlapping_number_counts.iter().sum()
+    }
 }
 
-pub fn solve_part_two(input: &str) -> u32 {
-    let parsed_input = parse_input(input);
-    let mut card_counts: BTreeMap<usize, u32> = BTreeMap::new();
-    // we say that we have one of each card
-    let num_cards = parsed_input.len();
-    for i in 0..num_cards {
-        card_counts.insert(i, 1);
+impl Card {
+    /// How many of the numbers we have are winning numbers.
+    pub fn matches(&self) -> usize {
+        let winning = self.winning.iter().collect::<HashSet<_>>();
+        self.have.iter().filter(|n| winning.contains(n)).count()
     }
-    for (group_index, cardgroups) in parsed_input.iter().enumerate() {
-        let my_numbers: BTreeSet<u32> = BTreeSet::from_iter(cardgroups[1].iter().cloned());
-        let winning_numbers: BTreeSet<u32> = BTreeSet::from_iter(cardgroups[0].iter().cloned());
-        let overlapping_numbers = winning_numbers.intersection(&my_numbers).collect_vec();
-        let overlap_count = overlapping_numbers.len();
-        // this means we have that many cards of the next n overlapping numbers
-        //println!("current group: {:?}", group_index);
-        let repeating_factor = card_counts.get(&group_index).unwrap();
-        for _i in 0..*repeating_factor {
-            for j in (group_index + 1)..=(group_index + overlap_count) {
-                //println!("adding card to group: {}", j);
-                let card_count = card_counts.get_mut(&j).unwrap();
-                //let original_count = *card_count;
-                // println!(
-                //     "j: {} increasing card count from {} to {}",
-                //     j,
-                //     original_count,
-                //     *card_count + 1
-                // );
-                *card_count += 1
-            }
+
+    /// One point for the first match, doubled for every match after it.
+    pub fn points(&self) -> u32 {
+        match self.matches() {
+            0 => 0,
+            count => 1 << (count - 1),
         }
+    }
+}
+
+/// How many copies of a card we end up with, and where they came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Copies {
+    pub id: u32,
+    /// Every copy, the original included.
+    pub count: u64,
+    /// The ids of earlier cards that won copies of this one, with how many each won.
+    pub won_from: Vec<(u32, u64)>,
+}
 
-        // println!(
-        //     "overlap: {}, group: {}, card_counts: {:?}",
-        //     overlap_count, group_index, card_counts
-        // );
+/// Plays out the cascade of won copies. By the time we reach a card all its
+/// copies are known, so its whole count goes to each of the next `matches` cards
+/// at once. Wins past the end of the table are dropped.
+pub fn cascade(cards: &[Card]) -> Vec<Copies> {
+    let mut copies = cards
+        .iter()
+        .map(|card| Copies {
+            id: card.id,
+            count: 1,
+            won_from: vec![],
+        })
+        .collect_vec();
+    for (i, card) in cards.iter().enumerate() {
+        let count = copies[i].count;
+        let last = (i + card.matches()).min(cards.len() - 1);
+        for won in &mut copies[i + 1..=last] {
+            won.count += count;
+            won.won_from.push((card.id, count));
+        }
     }
-    println!("final card counts: {:?}", card_counts);
-    card_counts.values().sum()
+    copies
 }
 
-fn parse_input(input: &str) -> Vec<Vec<Vec<u32>>> {
+pub fn parse_input(input: &str) -> Vec<Card> {
     input
         .lines()
-        .map(|line| {
-            // each line looks like Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
-            let (_id, cards) = line.split(':').collect_tuple().unwrap();
-            let numbers = cards.split('|').collect_vec();
-            numbers
-                .iter()
-                .map(|card| card.split_whitespace().collect_vec())
-                .map(|num| num.iter().map(|n| n.parse::<u32>().unwrap()).collect_vec())
-                .collect_vec()
-        })
+        .map(|line| Card::from_str(line).unwrap())
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_won_copies() {
+        let input = std::fs::read_to_string("input/4_easy.txt").unwrap();
+        let cards = parse_input(&input);
+        assert_eq!(cards[0].id, 1);
+        assert_eq!(cards[0].winning, vec![41, 48, 83, 86, 17]);
+        assert_eq!(cards[0].matches(), 4);
+
+        let copies = cascade(&cards);
+        let counts = copies.iter().map(|c| c.count).collect_vec();
+        assert_eq!(counts, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(copies[3].won_from, vec![(1, 1), (2, 2), (3, 4)]);
+        assert!(copies[5].won_from.is_empty());
+    }
+
+    #[test]
+    fn drops_wins_past_the_last_card() {
+        let cards = parse_input("Card 7: 1 2 | 1 2\nCard 9: 3 | 3");
+        let copies = cascade(&cards);
+        assert_eq!(copies[1].id, 9);
+        assert_eq!(copies[1].count, 2);
+        assert_eq!(copies[1].won_from, vec![(7, 1)]);
+    }
+
     #[test]
     fn solves_4_1_easy() {
         let input = std::fs::read_to_string("input/4_easy.txt").unwrap();
@@ -1,127 +1,174 @@
-use std::collections::{HashMap, VecDeque};
-
 use itertools::Itertools;
 
-use crate::utils::{reverse_cols, reverse_rows, transpose};
+use crate::utils::{nth_state, Grid};
 
 pub fn solve_part_one(input: &str) -> usize {
-    // transpose the input
-    let input = parse_input(input);
-    let transposed_input = transpose(input);
-    let result = move_rocks(transposed_input);
-    let result = transpose(result);
-    println!("Result: {:?}", result);
-    // In order to compute the result, we multiply the number of "O" in each row multiplied by rownum+1
-    let mut c = 0;
-    let num_lines = result.len();
-    for (i, line) in result.iter().enumerate() {
-        let num_stones = line.iter().filter(|c| **c == 'O').count();
-        println!("Row {} has {} stones", i, num_stones);
-        c += (num_lines - i) * num_stones;
-    }
-    c
+    let mut grid = parse_input(input);
+    tilt_north(&mut grid);
+    compute_load(&grid)
 }
 
-fn move_rocks(transposed_input: Vec<Vec<char>>) -> Vec<Vec<char>> {
-    let mut result = vec![];
-    for line in transposed_input {
-        let slided_line = slide_stone(line);
-        result.push(slided_line);
-    }
-    result
+pub fn solve_part_two(input: &str, times: usize) -> usize {
+    let grid = parse_input(input);
+    let mut template = grid.clone();
+    let rocks = nth_state(
+        Rocks::from_grid(&grid),
+        |rocks| {
+            rocks.place(&mut template);
+            spin_cycle(&mut template);
+            Rocks::from_grid(&template)
+        },
+        times,
+    );
+    rocks.place(&mut template);
+    compute_load(&template)
 }
 
-pub fn solve_part_two(input: &str, times: usize) -> usize {
-    let mut input = parse_input(input);
-    let mut current = 0;
-    let mut seen = HashMap::new();
-    while current < times {
-        current += 1;
-        input = solve_cycle_once(input);
-        if seen.contains_key(&input) {
-            let cycle_lengths = current - seen.get(&input).unwrap();
-            println!("Cycle length: {}", cycle_lengths);
-            let num_cycles = (times - current) / cycle_lengths;
-            println!("Number of cycles: {}", num_cycles);
-            current += num_cycles * cycle_lengths;
+fn compute_load(grid: &[Vec<char>]) -> usize {
+    let num_lines = grid.len();
+    grid.iter()
+        .enumerate()
+        .map(|(i, line)| (num_lines - i) * line.iter().filter(|c| **c == 'O').count())
+        .sum()
+}
+
+/// The positions of the round rocks, one bit per cell in row-major order.
+/// Cube rocks never move, so this is all that changes between spin cycles.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rocks {
+    bits: Vec<u64>,
+}
+
+impl Rocks {
+    pub fn from_grid(grid: &[Vec<char>]) -> Self {
+        let cols = grid[0].len();
+        let mut bits = vec![0; (grid.len() * cols).div_ceil(64)];
+        for (i, line) in grid.iter().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                if c == 'O' {
+                    let cell = i * cols + j;
+                    bits[cell / 64] |= 1 << (cell % 64);
+                }
+            }
+        }
+        Rocks { bits }
+    }
+
+    /// Writes the round rocks into `grid`, clearing every other non-cube cell.
+    pub fn place(&self, grid: &mut [Vec<char>]) {
+        let cols = grid[0].len();
+        for (i, line) in grid.iter_mut().enumerate() {
+            for (j, c) in line.iter_mut().enumerate() {
+                if *c != '#' {
+                    let cell = i * cols + j;
+                    *c = if self.bits[cell / 64] & (1 << (cell % 64)) != 0 {
+                        'O'
+                    } else {
+                        '.'
+                    };
+                }
+            }
         }
-        seen.insert(input.clone(), current);
     }
-    let load = compute_load(input);
-    println!("Load: {}", load);
-    load
 }
 
-fn compute_load(grid: Vec<Vec<char>>) -> usize {
-    let mut c = 0;
-    let num_lines = grid.len();
-    for (i, line) in grid.iter().enumerate() {
-        let num_stones = line.iter().filter(|c| **c == 'O').count();
-        //println!("Row {} has {} stones", i, num_stones);
-        c += (num_lines - i) * num_stones;
+/// Rolls every round rock as far north as it goes.
+/// Each column keeps the row the next rock will stop at, so this is a single pass.
+pub fn tilt_north(grid: &mut [Vec<char>]) {
+    let mut free = vec![0; grid[0].len()];
+    for i in 0..grid.len() {
+        for j in 0..grid[i].len() {
+            match grid[i][j] {
+                '#' => free[j] = i + 1,
+                'O' => {
+                    grid[i][j] = '.';
+                    grid[free[j]][j] = 'O';
+                    free[j] += 1;
+                }
+                _ => {}
+            }
+        }
     }
-    c
 }
 
-pub fn solve_cycle_once(input: Vec<Vec<char>>) -> Vec<Vec<char>> {
-    // in part two we compute the numbers based the following inputs
-    // 0. the transpose of the input (north) like we do in part 1
-    // 1. the column-wise reverse of the input (west)
-    // 2. the row-wise reverse of the input (south)
-    // 3. the original input (east)
-    let mut input = input.clone();
-    for i in 0..4 {
-        input = match i {
-            0 => {
-                let transposed_input = transpose(input);
-                let rocks = move_rocks(transposed_input);
-                transpose(rocks)
+/// Rolls every round rock as far south as it goes.
+pub fn tilt_south(grid: &mut [Vec<char>]) {
+    let rows = grid.len();
+    let mut free = vec![rows - 1; grid[0].len()];
+    for i in (0..rows).rev() {
+        for j in 0..grid[i].len() {
+            match grid[i][j] {
+                '#' => free[j] = i.wrapping_sub(1),
+                'O' => {
+                    grid[i][j] = '.';
+                    grid[free[j]][j] = 'O';
+                    free[j] = free[j].wrapping_sub(1);
+                }
+                _ => {}
             }
-            1 => move_rocks(input),
-            2 => {
-                //somehow this does not work
-                let reversed_rows_input = reverse_rows(input);
-                let transposed_input = transpose(reversed_rows_input);
-                let rocks = move_rocks(transposed_input);
-                let rocks = transpose(rocks);
-                reverse_rows(rocks)
+        }
+    }
+}
+
+/// Rolls every round rock as far west as it goes.
+pub fn tilt_west(grid: &mut [Vec<char>]) {
+    for line in grid.iter_mut() {
+        let mut free = 0;
+        for j in 0..line.len() {
+            match line[j] {
+                '#' => free = j + 1,
+                'O' => {
+                    line[j] = '.';
+                    line[free] = 'O';
+                    free += 1;
+                }
+                _ => {}
             }
-            3 => {
-                let reversed_cols_input = reverse_cols(input);
-                let rocks = move_rocks(reversed_cols_input);
-                reverse_cols(rocks)
+        }
+    }
+}
+
+/// Rolls every round rock as far east as it goes.
+pub fn tilt_east(grid: &mut [Vec<char>]) {
+    for line in grid.iter_mut() {
+        let mut free = line.len().wrapping_sub(1);
+        for j in (0..line.len()).rev() {
+            match line[j] {
+                '#' => free = j.wrapping_sub(1),
+                'O' => {
+                    line[j] = '.';
+                    line[free] = 'O';
+                    free = free.wrapping_sub(1);
+                }
+                _ => {}
             }
-            _ => panic!("Invalid input"),
-        };
-        //println!("At the end of cycle {} the grid is:", i,);
-        //pretty_print_grid(input.clone());
+        }
     }
+}
+
+/// Tilts north, west, south and then east.
+pub fn spin_cycle(grid: &mut [Vec<char>]) {
+    tilt_north(grid);
+    tilt_west(grid);
+    tilt_south(grid);
+    tilt_east(grid);
+}
+
+pub fn solve_cycle_once(input: Vec<Vec<char>>) -> Vec<Vec<char>> {
+    let mut input = input;
+    spin_cycle(&mut input);
     input
 }
 
 /// This function slides all stone "O" to the left or to the closest # on the right.
+#[cfg(test)]
 fn slide_stone(input: Vec<char>) -> Vec<char> {
-    let mut queue = VecDeque::from(input);
-    queue.push_front('#');
-    let mut result = vec!['.'; queue.len()];
-    let mut group_start = 0;
-    let mut num_dots = 0;
-    for (i, c) in queue.iter().enumerate() {
-        if *c == '#' {
-            result[i] = *c;
-            group_start = i;
-            num_dots = 0;
-        } else if *c == '.' {
-            num_dots += 1;
-        } else {
-            let next_pos = group_start + (i - num_dots - group_start);
-            result[next_pos] = *c;
-        }
-    }
-    return result[1..].iter().copied().collect_vec();
+    let mut grid = vec![input];
+    tilt_west(&mut grid);
+    grid.pop().unwrap()
 }
 
-fn parse_input(input: &str) -> Vec<Vec<char>> {
+fn parse_input(input: &str) -> Grid<char> {
     input
         .lines()
         .map(|line| line.trim().chars().collect_vec())
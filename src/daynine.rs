@@ -1,59 +1,137 @@
-pub fn solve_part_one(input: &str) -> i32 {
+use num::{BigInt, Zero};
+
+pub fn solve_part_one(input: &str) -> i64 {
     let parsed_input = parse(input);
     let extrapolated = parsed_input
         .iter()
-        .map(extrapolate)
-        .collect::<Vec<_>>();
-    extrapolated.iter().sum()
+        .map(|numbers| Polynomial::fit(numbers).unwrap().at(numbers.len() as i64))
+        .sum::<BigInt>();
+    extrapolated.try_into().unwrap()
 }
 
-pub fn solve_part_two(input: &str) -> i32 {
+pub fn solve_part_two(input: &str) -> i64 {
     let parsed = parse(input);
     let extrapolated = parsed
         .iter()
-        .map(|numbers| extrapolate_second(numbers))
-        .collect::<Vec<_>>();
-    extrapolated.iter().sum()
+        .map(|numbers| Polynomial::fit(numbers).unwrap().at(-1))
+        .sum::<BigInt>();
+    extrapolated.try_into().unwrap()
 }
 
-fn parse(input: &str) -> Vec<Vec<i32>> {
+fn parse(input: &str) -> Vec<Vec<i64>> {
     input
         .lines()
         .map(|line| {
             line.split_whitespace()
-                .map(|s| s.parse::<i32>().unwrap())
+                .map(|s| s.parse::<i64>().unwrap())
                 .collect()
         })
         .collect()
 }
 
-fn extrapolate(numbers: &Vec<i32>) -> i32 {
-    let diffs = find_diffs(numbers);
-    if diffs.iter().all(|&x| x == 0) {
-        *numbers.last().unwrap()
-    } else {
-        *numbers.last().unwrap() + extrapolate(&diffs)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SequenceError {
+    Empty,
+    /// Every row of the difference table has a non-zero entry, so the
+    /// values don't pin down a polynomial.
+    NoZeroRow,
+}
+
+impl std::fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceError::Empty => write!(f, "the sequence is empty"),
+            SequenceError::NoZeroRow => {
+                write!(f, "the differences never reach a row of zeroes")
+            }
+        }
     }
 }
 
-fn extrapolate_second(numbers: &[i32]) -> i32 {
-    let diffs = find_diffs(numbers);
-    if diffs.iter().all(|&x| x == 0) {
-        *numbers.first().unwrap()
-    } else {
-        *numbers.first().unwrap() - extrapolate_second(&diffs)
+impl std::error::Error for SequenceError {}
+
+/// The polynomial through a sequence, in Newton forward difference form:
+/// the value at index `n` is the sum over `k` of `C(n, k)` times the first
+/// entry of the `k`th difference row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    leading_differences: Vec<BigInt>,
+}
+
+impl Polynomial {
+    /// Fits the polynomial to `values`, where `values[i]` is the value at index `i`.
+    pub fn fit(values: &[i64]) -> Result<Self, SequenceError> {
+        if values.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+        let mut row = values.iter().map(|&v| BigInt::from(v)).collect::<Vec<_>>();
+        let mut leading_differences = vec![];
+        while !row.iter().all(|x| x.is_zero()) {
+            if row.len() == 1 {
+                return Err(SequenceError::NoZeroRow);
+            }
+            leading_differences.push(row[0].clone());
+            row = find_diffs(&row);
+        }
+        Ok(Polynomial {
+            leading_differences,
+        })
+    }
+
+    /// The degree of the polynomial. The zero polynomial has degree 0 as well.
+    pub fn degree(&self) -> usize {
+        self.leading_differences.len().saturating_sub(1)
+    }
+
+    /// The value at `index`, which may lie before or after the known values.
+    pub fn at(&self, index: i64) -> BigInt {
+        let n = BigInt::from(index);
+        let mut binomial = BigInt::from(1);
+        let mut total = BigInt::zero();
+        for (k, difference) in self.leading_differences.iter().enumerate() {
+            total += &binomial * difference;
+            // C(n, k + 1) = C(n, k) * (n - k) / (k + 1), which divides exactly
+            binomial = binomial * (&n - k) / (k + 1);
+        }
+        total
     }
 }
 
-fn find_diffs(numbers: &[i32]) -> Vec<i32> {
+fn find_diffs(numbers: &[BigInt]) -> Vec<BigInt> {
     numbers
         .windows(2)
-        .map(|window| window[1] - window[0])
+        .map(|window| &window[1] - &window[0])
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_exact_polynomials() {
+        let cubes = (0..6).map(|x: i64| x * x * x - 2 * x).collect::<Vec<_>>();
+        let polynomial = Polynomial::fit(&cubes).unwrap();
+        assert_eq!(polynomial.degree(), 3);
+        assert_eq!(polynomial.at(-3), BigInt::from(-21));
+        let far = BigInt::from(10i64.pow(9));
+        assert_eq!(polynomial.at(10i64.pow(9)), &far * &far * &far - 2 * &far);
+        assert_eq!(
+            Polynomial::fit(&[10, 13, 16, 21, 30, 45]).unwrap().at(-1),
+            BigInt::from(5)
+        );
+    }
+
+    #[test]
+    fn rejects_sequences_without_zero_row() {
+        assert_eq!(Polynomial::fit(&[]), Err(SequenceError::Empty));
+        assert_eq!(
+            Polynomial::fit(&[1, 2, 4, 8]),
+            Err(SequenceError::NoZeroRow)
+        );
+        assert_eq!(Polynomial::fit(&[7, 7]).unwrap().degree(), 0);
+    }
+
     #[test]
     fn solves_9_1_easy() {
         let input = std::fs::read_to_string("input/9_easy.txt").unwrap();
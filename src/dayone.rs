@@ -1,81 +1,218 @@
-use rayon::prelude::*;
+use std::{cmp::Reverse, collections::VecDeque};
 
 pub fn solve_part_one(input: &str) -> u32 {
-    input
-        .trim()
-        .par_lines()
-        .map(|line| {
-            let digits: Vec<_> = line
-                .chars()
-                .filter(|c| c.is_ascii_digit())
-                .map(|c| c.to_digit(10).unwrap())
-                .collect();
-            println!("Digits is {:?}", digits);
-            let product = (digits.first().unwrap() * 10) + digits.last().unwrap();
-            product
-        })
-        .sum()
+    calibrate(input, &Matcher::digits()).unwrap()
 }
 
-fn find_all(input: &str, substring: &str) -> Vec<usize> {
-    let mut positions = vec![];
-    let mut start = 0;
-    while let Some(pos) = input[start..].find(substring) {
-        positions.push(start + pos);
-        start += pos + 1;
+pub fn solve_part_two(input: &str) -> u32 {
+    calibrate(input, &Matcher::spelled_digits()).unwrap()
+}
+
+/// Sums the calibration value of every line, or reports the lines where
+/// `matcher` finds nothing.
+pub fn calibrate(input: &str, matcher: &Matcher) -> Result<u32, CalibrationError> {
+    let mut total = 0;
+    let mut unmatched = vec![];
+    for (number, line) in input.trim().lines().enumerate() {
+        match matcher.calibration_value(line) {
+            Some(value) => total += value,
+            None => unmatched.push(number + 1),
+        }
+    }
+    if unmatched.is_empty() {
+        Ok(total)
+    } else {
+        Err(CalibrationError { lines: unmatched })
     }
-    positions
 }
 
-pub fn solve_part_two(input: &str) -> u32 {
-    let words = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
-    input
-        .trim()
-        .par_lines()
-        .map(|line| {
-            let mut digit_pos_pairs = vec![];
-            for word in words.iter() {
-                for index in find_all(line, word) {
-                    digit_pos_pairs.push((convert_to_num(word), index));
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalibrationError {
+    /// Line numbers, counted from one, without a single match.
+    pub lines: Vec<usize>,
+}
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no digits on lines {:?}", self.lines)
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    /// Byte offsets of the matched word in the line.
+    pub start: usize,
+    pub end: usize,
+    pub value: u32,
+}
+
+const ENGLISH: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+/// Aho–Corasick automaton over a vocabulary of words, each standing for a value.
+/// Finds every occurrence of every word, overlapping ones included, in one pass.
+pub struct Matcher {
+    /// Next state for every state and byte, failure links already folded in.
+    transitions: Vec<[usize; 256]>,
+    /// Indices into `words` of every word ending at each state.
+    outputs: Vec<Vec<usize>>,
+    words: Vec<(String, u32)>,
+}
+
+impl Matcher {
+    pub fn new<S: AsRef<str>>(vocabulary: &[(S, u32)]) -> Self {
+        let words = vocabulary
+            .iter()
+            .map(|(word, value)| (word.as_ref().to_string(), *value))
+            .filter(|(word, _)| !word.is_empty())
+            .collect::<Vec<_>>();
+
+        // the trie, with 0 standing for "no edge" since nothing points back at the root
+        let mut transitions = vec![[0; 256]];
+        let mut outputs = vec![vec![]];
+        for (index, (word, _)) in words.iter().enumerate() {
+            let mut state = 0;
+            for &byte in word.as_bytes() {
+                if transitions[state][byte as usize] == 0 {
+                    transitions.push([0; 256]);
+                    outputs.push(vec![]);
+                    transitions[state][byte as usize] = transitions.len() - 1;
                 }
+                state = transitions[state][byte as usize];
             }
-            let digits_pos: Vec<_> = line
-                .chars()
-                .enumerate()
-                .filter(|(_pos, c)| c.is_ascii_digit())
-                .map(|(pos, c)| (c.to_digit(10).unwrap(), pos))
-                .collect();
-            digit_pos_pairs.extend(digits_pos);
-            digit_pos_pairs.sort_by(|a, b| a.1.cmp(&b.1));
-
-            digit_pos_pairs
-        })
-        .map(|pairs| {
-            let numbers: Vec<_> = pairs.iter().map(|p| p.0).collect();
-            let product = (numbers.first().unwrap() * 10) + numbers.last().unwrap();
-            product
-        })
-        .sum()
-}
+            outputs[state].push(index);
+        }
+
+        // breadth first, so a state's failure link is complete before its children need it
+        let mut failure = vec![0; transitions.len()];
+        let mut queue = transitions[0]
+            .iter()
+            .copied()
+            .filter(|&child| child != 0)
+            .collect::<VecDeque<_>>();
+        while let Some(state) = queue.pop_front() {
+            let inherited = outputs[failure[state]].clone();
+            outputs[state].extend(inherited);
+            let fallbacks = transitions[failure[state]];
+            for (next, fallback) in transitions[state].iter_mut().zip(fallbacks) {
+                if *next == 0 {
+                    *next = fallback;
+                } else {
+                    failure[*next] = fallback;
+                    queue.push_back(*next);
+                }
+            }
+        }
 
-fn convert_to_num(word: &str) -> u32 {
-    match word {
-        "zero" => 0,
-        "one" => 1,
-        "two" => 2,
-        "three" => 3,
-        "four" => 4,
-        "five" => 5,
-        "six" => 6,
-        "seven" => 7,
-        "eight" => 8,
-        "nine" => 9,
-        _ => panic!("Invalid word"),
+        Matcher {
+            transitions,
+            outputs,
+            words,
+        }
+    }
+
+    /// Only the digits `0` to `9`.
+    pub fn digits() -> Self {
+        let digits = (0..10).map(|d| (d.to_string(), d)).collect::<Vec<_>>();
+        Matcher::new(&digits)
+    }
+
+    /// The digits along with their English names.
+    pub fn spelled_digits() -> Self {
+        let words = (0..10)
+            .map(|d| (d.to_string(), d))
+            .chain(ENGLISH.iter().zip(0..).map(|(w, d)| (w.to_string(), d)))
+            .collect::<Vec<_>>();
+        Matcher::new(&words)
+    }
+
+    /// Every match in `line`, ordered by where it ends.
+    pub fn matches<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Match> + 'a {
+        line.bytes()
+            .scan(0, move |state, byte| {
+                *state = self.transitions[*state][byte as usize];
+                Some(*state)
+            })
+            .enumerate()
+            .flat_map(move |(i, state)| {
+                self.outputs[state].iter().map(move |&index| {
+                    let (word, value) = &self.words[index];
+                    Match {
+                        start: i + 1 - word.len(),
+                        end: i + 1,
+                        value: *value,
+                    }
+                })
+            })
+    }
+
+    /// The first and last match of `line`, by where they start. Overlapping words
+    /// both count, so `twone` reads as two then one. Of two words starting at the
+    /// same place, the longer one wins.
+    pub fn first_and_last(&self, line: &str) -> Option<(Match, Match)> {
+        let matches = self.matches(line).collect::<Vec<_>>();
+        let first = matches.iter().min_by_key(|m| (m.start, Reverse(m.end)))?;
+        let last = matches.iter().max_by_key(|m| (m.start, m.end))?;
+        Some((*first, *last))
+    }
+
+    /// Ten times the first value plus the last one.
+    pub fn calibration_value(&self, line: &str) -> Option<u32> {
+        self.first_and_last(line)
+            .map(|(first, last)| first.value * 10 + last.value)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_overlapping_words() {
+        let matcher = Matcher::spelled_digits();
+        assert_eq!(matcher.calibration_value("twone"), Some(21));
+        assert_eq!(matcher.calibration_value("eightwothree"), Some(83));
+        assert_eq!(matcher.calibration_value("oneight"), Some(18));
+        assert_eq!(matcher.matches("sevenine").count(), 2);
+    }
+
+    #[test]
+    fn uses_custom_vocabularies() {
+        let german = Matcher::new(&[("eins", 1), ("zwei", 2), ("drei", 3), ("fünf", 5)]);
+        assert_eq!(german.calibration_value("fünfxzweins"), Some(51));
+
+        let teens = ["ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen"]
+            .iter()
+            .zip(10..)
+            .map(|(word, value)| (word.to_string(), value))
+            .chain(ENGLISH.iter().zip(0..).map(|(w, d)| (w.to_string(), d)))
+            .collect::<Vec<_>>();
+        let first_and_last = Matcher::new(&teens)
+            .first_and_last("fourteenine")
+            .map(|(first, last)| (first.value, last.value));
+        assert_eq!(first_and_last, Some((14, 9)));
+    }
+
+    #[test]
+    fn reports_lines_without_matches() {
+        let input = "1abc2\nnothing\nthree3\nnope";
+        assert_eq!(
+            calibrate(input, &Matcher::digits()),
+            Err(CalibrationError { lines: vec![2, 4] })
+        );
+        assert_eq!(
+            calibrate(input, &Matcher::spelled_digits()),
+            Err(CalibrationError { lines: vec![2, 4] })
+        );
+        assert_eq!(
+            calibrate("1abc2\nthree3", &Matcher::spelled_digits()),
+            Ok(45)
+        );
+    }
+
     #[test]
     fn solves_1_1_easy() {
         let input = std::fs::read_to_string("input/1_easy.txt").unwrap();
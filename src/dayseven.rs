@@ -2,30 +2,7 @@ use core::fmt::Display;
 use itertools::Itertools;
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
-pub struct Hand(Vec<u64>);
-
-impl Display for Hand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let cards = self.0.clone();
-        let cards: Vec<String> = cards.iter().map(|c| card_string(*c)).collect();
-        write!(f, "{}", cards.join(""))
-    }
-}
-
-fn card_string(card: u64) -> String {
-    match card {
-        14 => "A".to_string(),
-        13 => "K".to_string(),
-        12 => "Q".to_string(),
-        11 => "J".to_string(),
-        10 => "T".to_string(),
-        1 => "J".to_string(),
-        _ => card.to_string(),
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Category {
     HighCard,
     OnePair,
@@ -36,86 +13,235 @@ pub enum Category {
     FiveOfAKind,
 }
 
-pub fn parse_line(line: &str, part_two: bool) -> (Hand, u64) {
-    let (raw_cards, raw_score) = line.split_ascii_whitespace().collect_tuple().unwrap();
-    let cards = raw_cards
-        .chars()
-        .map(|c| card_value(c, part_two))
-        .collect_vec();
-    let score = raw_score.parse::<u64>().unwrap();
-    (Hand(cards), score)
-}
-
-fn card_value(card: char, part_two: bool) -> u64 {
-    match card {
-        'A' => 14,
-        'K' => 13,
-        'Q' => 12,
-        'J' => {
-            if part_two {
-                1
-            } else {
-                11
+/// How hands are read and ranked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ruleset {
+    /// Every card, weakest first.
+    pub order: Vec<char>,
+    /// Cards that stand in for whichever card makes the hand strongest.
+    pub wild: Vec<char>,
+    pub hand_size: usize,
+    /// Group sizes, largest first, that a hand needs to reach each category.
+    /// A hand with groups `[3, 1, 1]` meets both `[3]` and `[]`, and ranks as
+    /// the highest category it meets.
+    pub categories: Vec<(Vec<usize>, Category)>,
+}
+
+impl Ruleset {
+    pub fn standard() -> Self {
+        Ruleset {
+            order: "23456789TJQKA".chars().collect_vec(),
+            wild: vec![],
+            hand_size: 5,
+            categories: vec![
+                (vec![], Category::HighCard),
+                (vec![2], Category::OnePair),
+                (vec![2, 2], Category::TwoPair),
+                (vec![3], Category::ThreeOfAKind),
+                (vec![3, 2], Category::FullHouse),
+                (vec![4], Category::FourOfAKind),
+                (vec![5], Category::FiveOfAKind),
+            ],
+        }
+    }
+
+    /// The part two rules: `J` is a joker, and the weakest card on its own.
+    pub fn jokers() -> Self {
+        Ruleset {
+            order: "J23456789TQKA".chars().collect_vec(),
+            wild: vec!['J'],
+            ..Ruleset::standard()
+        }
+    }
+
+    pub fn card_value(&self, card: char) -> Option<u64> {
+        self.order
+            .iter()
+            .position(|&c| c == card)
+            .map(|index| index as u64)
+    }
+
+    fn category_of(&self, cards: &[char]) -> Category {
+        let groups = cards
+            .iter()
+            .counts()
+            .into_values()
+            .sorted()
+            .rev()
+            .collect_vec();
+        self.categories
+            .iter()
+            .filter(|(pattern, _)| {
+                pattern.len() <= groups.len() && pattern.iter().zip(&groups).all(|(p, g)| p <= g)
+            })
+            .map(|(_, category)| *category)
+            .max()
+            .unwrap_or(Category::HighCard)
+    }
+
+    /// Works out the category of `cards`, replacing every wild card with
+    /// whatever makes the category highest. Every assignment of natural cards
+    /// to the wild cards is tried, since with a custom category table it can
+    /// pay to split them. If it is as good as any, wild cards join the largest
+    /// group of natural cards, preferring the strongest card on ties, or the
+    /// strongest card overall if the hand is all wild.
+    pub fn explain(&self, cards: &str) -> Result<Explanation, HandError> {
+        let cards = cards.chars().collect_vec();
+        if cards.len() != self.hand_size {
+            return Err(HandError::WrongSize {
+                expected: self.hand_size,
+                found: cards.len(),
+            });
+        }
+        let mut values = vec![];
+        for &card in &cards {
+            values.push(self.card_value(card).ok_or(HandError::UnknownCard(card))?);
+        }
+
+        let natural = cards.iter().filter(|c| !self.wild.contains(c));
+        let counts: BTreeMap<char, usize> = natural.copied().counts().into_iter().collect();
+        let target = counts
+            .iter()
+            .max_by_key(|&(&card, &count)| (count, self.card_value(card)))
+            .map(|(&card, _)| card)
+            .unwrap_or(*self.order.last().unwrap());
+        let wild_positions = (0..cards.len())
+            .filter(|&i| self.wild.contains(&cards[i]))
+            .collect_vec();
+        let resolve = |replacements: &[char]| {
+            let mut resolved = cards.clone();
+            for (&position, &card) in wild_positions.iter().zip(replacements) {
+                resolved[position] = card;
+            }
+            resolved
+        };
+
+        let mut resolved = resolve(&vec![target; wild_positions.len()]);
+        let mut best = self.category_of(&resolved);
+        // strongest first, so ties go to the stronger cards
+        let naturals = self.order.iter().rev().filter(|c| !self.wild.contains(c));
+        for replacements in naturals.combinations_with_replacement(wild_positions.len()) {
+            let candidate = resolve(&replacements.into_iter().copied().collect_vec());
+            let category = self.category_of(&candidate);
+            if category > best {
+                best = category;
+                resolved = candidate;
             }
         }
-        'T' => 10,
-        _ => card.to_digit(10).unwrap() as u64,
+        let substitutions = wild_positions
+            .iter()
+            .filter(|&&position| resolved[position] != cards[position])
+            .map(|&position| (position, cards[position], resolved[position]))
+            .collect_vec();
+
+        let category = self.category_of(&resolved);
+        Ok(Explanation {
+            hand: Hand {
+                category,
+                values,
+                cards,
+            },
+            resolved,
+            substitutions,
+        })
+    }
+
+    pub fn hand(&self, cards: &str) -> Result<Hand, HandError> {
+        self.explain(cards).map(|explanation| explanation.hand)
     }
 }
 
-impl Hand {
-    pub fn category(&self, part_two: bool) -> Category {
-        let (counts, num_jokers) =
-            self.0
-                .iter()
-                .fold((BTreeMap::new(), 0), |(mut counts, num_jokers), card| {
-                    if part_two && *card == 1 {
-                        (counts, num_jokers + 1)
-                    } else {
-                        *counts.entry(*card).or_insert(0) += 1;
-                        (counts, num_jokers)
-                    }
-                });
-
-        let mut freqs = counts.values().copied().collect_vec();
-        freqs.sort();
-        if let Some(last_item) = freqs.last_mut() {
-            *last_item += num_jokers;
-        } else {
-            freqs.push(num_jokers);
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandError {
+    WrongSize { expected: usize, found: usize },
+    UnknownCard(char),
+}
 
-        match *freqs {
-            [1, 1, 1, 1, 1] => Category::HighCard,
-            [1, 1, 1, 2] => Category::OnePair,
-            [1, 2, 2] => Category::TwoPair,
-            [1, 1, 3] => Category::ThreeOfAKind,
-            [2, 3] => Category::FullHouse,
-            [1, 4] => Category::FourOfAKind,
-            [5] => Category::FiveOfAKind,
-            _ => panic!("Invalid hand: {:?}", self),
+impl Display for HandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandError::WrongSize { expected, found } => {
+                write!(f, "expected {} cards, found {}", expected, found)
+            }
+            HandError::UnknownCard(card) => write!(f, "unknown card `{}`", card),
         }
     }
 }
 
-pub fn solve(input: &str, part_two: bool) -> u32 {
-    let mut hands = input
+impl std::error::Error for HandError {}
+
+/// A hand as ranked by the [`Ruleset`] that built it. Hands compare by
+/// category first and then card by card, so they sort weakest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hand {
+    category: Category,
+    values: Vec<u64>,
+    cards: Vec<char>,
+}
+
+impl Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cards.iter().collect::<String>())
+    }
+}
+
+impl Hand {
+    pub fn category(&self) -> Category {
+        self.category
+    }
+
+    pub fn cards(&self) -> &[char] {
+        &self.cards
+    }
+}
+
+/// Why a hand ranks where it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub hand: Hand,
+    /// The hand with every wild card replaced.
+    pub resolved: Vec<char>,
+    /// `(position, wild card, replacement)` for every wild card that changed.
+    pub substitutions: Vec<(usize, char, char)>,
+}
+
+fn ruleset(part_two: bool) -> Ruleset {
+    if part_two {
+        Ruleset::jokers()
+    } else {
+        Ruleset::standard()
+    }
+}
+
+pub fn parse_line(line: &str, part_two: bool) -> (Hand, u64) {
+    parse_line_with(line, &ruleset(part_two))
+}
+
+fn parse_line_with(line: &str, ruleset: &Ruleset) -> (Hand, u64) {
+    let (raw_cards, raw_score) = line.split_ascii_whitespace().collect_tuple().unwrap();
+    let hand = ruleset.hand(raw_cards).unwrap();
+    let score = raw_score.parse::<u64>().unwrap();
+    (hand, score)
+}
+
+/// The strength of `card` under the part one or part two rules.
+pub fn card_value(card: char, part_two: bool) -> u64 {
+    ruleset(part_two).card_value(card).unwrap()
+}
+
+pub fn solve_with(input: &str, ruleset: &Ruleset) -> u64 {
+    input
         .lines()
-        .map(|line| parse_line(line, part_two))
-        .collect_vec();
-    hands.sort_by(|a, b| {
-        let category = a.0.category(part_two);
-        let other_category = b.0.category(part_two);
-        let (cat, num) = (category, a.0 .0.clone());
-        let (other_cat, other_num) = (other_category, b.0 .0.clone());
-        (cat, num).partial_cmp(&(other_cat, other_num)).unwrap()
-    });
-    let mut score = 0;
-    for (i, hand) in hands.iter().enumerate() {
-        println!("{}: {}:{}", i + 1, hand.0, hand.1);
-        score += (i + 1) as u32 * hand.1 as u32;
-    }
-    score
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_line_with(line, ruleset))
+        .sorted()
+        .enumerate()
+        .map(|(i, (_, bid))| (i as u64 + 1) * bid)
+        .sum()
+}
+
+pub fn solve(input: &str, part_two: bool) -> u32 {
+    solve_with(input, &ruleset(part_two)) as u32
 }
 
 pub fn solve_part_one(input: &str) -> u32 {
@@ -126,8 +252,187 @@ pub fn solve_part_two(input: &str) -> u32 {
     solve(input, true)
 }
 
+/// Exact odds for hands dealt uniformly at random, every card drawn
+/// independently from the ranks of the ruleset.
+pub mod odds {
+    use std::collections::BTreeMap;
+
+    use itertools::Itertools;
+    use num::rational::Ratio;
+
+    use super::{Category, HandError, Ruleset};
+
+    /// The probability of each category under the part one or part two rules.
+    pub fn category_probabilities(part_two: bool) -> BTreeMap<Category, Ratio<u64>> {
+        category_probabilities_with(&super::ruleset(part_two))
+    }
+
+    /// The probability of each category, found by enumerating every multiset
+    /// of cards once and weighting it by the number of orders it can be dealt in.
+    pub fn category_probabilities_with(ruleset: &Ruleset) -> BTreeMap<Category, Ratio<u64>> {
+        let factorial = |n: usize| (1..=n as u64).product::<u64>();
+        let total = (ruleset.order.len() as u64).pow(ruleset.hand_size as u32);
+
+        let mut counts = BTreeMap::new();
+        for cards in ruleset
+            .order
+            .iter()
+            .combinations_with_replacement(ruleset.hand_size)
+        {
+            let orderings = factorial(ruleset.hand_size)
+                / cards
+                    .iter()
+                    .counts()
+                    .values()
+                    .map(|&n| factorial(n))
+                    .product::<u64>();
+            let hand = ruleset
+                .hand(&cards.into_iter().collect::<String>())
+                .unwrap();
+            *counts.entry(hand.category()).or_insert(0) += orderings;
+        }
+        counts
+            .into_iter()
+            .map(|(category, count)| (category, Ratio::new(count, total)))
+            .collect()
+    }
+
+    /// The expected total winnings when the bids are handed to random hands.
+    /// Every hand is equally likely to end up at any rank, so each bid is
+    /// multiplied by the average rank `(n + 1) / 2`.
+    pub fn expected_winnings(bids: &[u64]) -> Ratio<u64> {
+        let n = bids.len() as u64;
+        Ratio::new(bids.iter().sum::<u64>() * (n + 1), 2)
+    }
+
+    /// The winnings of the bids when they are placed on `hands` in the order given.
+    pub fn winnings(hands: &[&str], bids: &[u64], part_two: bool) -> Result<u64, HandError> {
+        let ruleset = super::ruleset(part_two);
+        let mut ranked = hands
+            .iter()
+            .zip(bids)
+            .map(|(cards, &bid)| Ok((ruleset.hand(cards)?, bid)))
+            .collect::<Result<Vec<_>, HandError>>()?;
+        ranked.sort();
+        Ok(ranked
+            .iter()
+            .enumerate()
+            .map(|(i, (_, bid))| (i as u64 + 1) * bid)
+            .sum())
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use num::rational::Ratio;
+
+    use super::*;
+
+    #[test]
+    fn explains_joker_substitution() {
+        let rules = Ruleset::jokers();
+        let explanation = rules.explain("KTJJT").unwrap();
+        assert_eq!(explanation.hand.category(), Category::FourOfAKind);
+        assert_eq!(
+            explanation.substitutions,
+            vec![(2, 'J', 'T'), (3, 'J', 'T')]
+        );
+        assert_eq!(rules.explain("JJJJJ").unwrap().resolved, vec!['A'; 5]);
+        assert!(rules.hand("JKKK2").unwrap() < rules.hand("QQQQ2").unwrap());
+        let standard = Ruleset::standard().explain("JKKK2").unwrap();
+        assert_eq!(standard.hand.category(), Category::ThreeOfAKind);
+        assert!(standard.substitutions.is_empty());
+    }
+
+    #[test]
+    fn picks_the_best_substitution_for_custom_tables() {
+        // two pairs outrank three of a kind here, so the joker should pair the K
+        let mut rules = Ruleset::jokers();
+        rules.categories = vec![
+            (vec![], Category::HighCard),
+            (vec![2], Category::OnePair),
+            (vec![3], Category::TwoPair),
+            (vec![2, 2], Category::ThreeOfAKind),
+        ];
+        let explanation = rules.explain("AAK2J").unwrap();
+        assert_eq!(explanation.hand.category(), Category::ThreeOfAKind);
+        assert_eq!(explanation.substitutions, vec![(4, 'J', 'K')]);
+
+        // with two jokers the best is to split them, pairing up two of the singles
+        let explanation = rules.explain("AKJJ2").unwrap();
+        assert_eq!(explanation.hand.category(), Category::ThreeOfAKind);
+        assert_eq!(explanation.substitutions.len(), 2);
+    }
+
+    #[test]
+    fn supports_other_hand_sizes() {
+        let rules = Ruleset {
+            hand_size: 3,
+            ..Ruleset::standard()
+        };
+        assert_eq!(
+            rules.hand("KKK").unwrap().category(),
+            Category::ThreeOfAKind
+        );
+        assert_eq!(
+            Ruleset::standard().hand("KKK"),
+            Err(HandError::WrongSize {
+                expected: 5,
+                found: 3
+            })
+        );
+        assert_eq!(
+            Ruleset::standard().hand("KKKX1"),
+            Err(HandError::UnknownCard('X'))
+        );
+    }
+
+    #[test]
+    fn matches_known_hand_frequencies() {
+        let probabilities = odds::category_probabilities(false);
+        let total = 13u64.pow(5);
+        let expected = [
+            (Category::HighCard, 154440),
+            (Category::OnePair, 171600),
+            (Category::TwoPair, 25740),
+            (Category::ThreeOfAKind, 17160),
+            (Category::FullHouse, 1560),
+            (Category::FourOfAKind, 780),
+            (Category::FiveOfAKind, 13),
+        ];
+        for (category, count) in expected {
+            assert_eq!(probabilities[&category], Ratio::new(count, total));
+        }
+
+        let jokers = odds::category_probabilities(true);
+        assert_eq!(jokers.values().sum::<Ratio<u64>>(), Ratio::from_integer(1));
+        assert_eq!(
+            jokers[&Category::FiveOfAKind],
+            Ratio::new(12 * 31 + 1, total)
+        );
+    }
+
+    #[test]
+    fn expected_winnings_matches_every_shuffle() {
+        let hands = ["32T3K", "T55J5", "KK677", "KTJJT", "QQQJA"];
+        let bids = [765, 684, 28, 220, 483];
+        let permutations = bids.iter().copied().permutations(bids.len()).collect_vec();
+        let total: u64 = permutations
+            .iter()
+            .map(|shuffled| odds::winnings(&hands, shuffled, false).unwrap())
+            .sum();
+        assert_eq!(odds::winnings(&hands, &bids, false), Ok(6440));
+        assert_eq!(odds::winnings(&hands, &bids, true), Ok(5905));
+        assert_eq!(
+            odds::winnings(&["32T3X"], &[1], false),
+            Err(HandError::UnknownCard('X'))
+        );
+        assert_eq!(
+            odds::expected_winnings(&bids),
+            Ratio::new(total, permutations.len() as u64)
+        );
+    }
+
     #[test]
     fn solves_7_1_easy() {
         let input = std::fs::read_to_string("input/7_easy.txt").unwrap();
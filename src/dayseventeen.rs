@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::BinaryHeap;
 
 use itertools::Itertools;
 
@@ -14,28 +14,53 @@ pub fn solve_part_two(input: &str) -> usize {
     result.unwrap()
 }
 
+/// Unit vectors for north, east, south and west, in that order.
+/// A direction is stored as an index into this array.
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+/// Where to go and how the crucible is allowed to move.
+/// The crucible has to move at least `min_steps` and at most `max_steps`
+/// in a straight line before turning, and may only stop after `min_steps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constraints {
+    pub start: (usize, usize),
+    pub goal: (usize, usize),
+    pub min_steps: usize,
+    pub max_steps: usize,
+    /// Use A* with a Manhattan distance times the cheapest cell as the heuristic.
+    pub a_star: bool,
+}
+
+impl Constraints {
+    /// Constraints going from the top-left to the bottom-right of the grid.
+    pub fn new(grid: &[Vec<usize>], min_steps: usize, max_steps: usize) -> Self {
+        Constraints {
+            start: (0, 0),
+            goal: (grid.len() - 1, grid[0].len() - 1),
+            min_steps,
+            max_steps,
+            a_star: false,
+        }
+    }
+}
+
+/// A cheapest route: its total heat loss and every cell visited, start and goal included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub cost: usize,
+    pub path: Vec<(usize, usize)>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 struct State {
+    priority: usize,
     cost: usize,
-    position: (usize, usize),
-    direction: (i32, i32),
-    steps: usize,
-}
-
-impl State {
-    fn new(cost: usize, position: (usize, usize), direction: (i32, i32), steps: usize) -> Self {
-        Self {
-            cost,
-            position,
-            direction,
-            steps,
-        }
-    }
+    index: usize,
 }
 
 impl Ord for State {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.cost.cmp(&self.cost)
+        other.priority.cmp(&self.priority)
     }
 }
 
@@ -45,41 +70,85 @@ impl PartialOrd for State {
     }
 }
 
-/// Returns the shortest path from start to end in the grid.
-/// In doing so it ensures that you have only moved in a given
-/// direction for the number of steps between step_bound.start and step_bound.end
-/// (inclusive).
-/// If no path is found, returns None.
-fn dijkstras_with_step_bound(
-    grid: &Vec<Vec<usize>>,
-    min_steps: usize,
+/// Packs (position, direction, steps) into an index of the dense state arrays.
+struct StateSpace {
+    cols: usize,
     max_steps: usize,
-) -> Option<usize> {
-    let mut queue = std::collections::BinaryHeap::new();
-    // keep track of (position, direction, steps)
-    let mut visited: BTreeSet<((usize, usize), (i32, i32), usize)> =
-        std::collections::BTreeSet::new();
-    queue.push(State::new(0, (0, 0), (0, 1), 0));
-    let direction_unit_vectors = vec![(-1, 0), (0, 1), (1, 0), (0, -1)];
-    while let Some(State {
-        cost,
-        position,
-        direction,
-        steps,
-    }) = queue.pop()
-    {
-        if position == (grid.len() - 1, grid[0].len() - 1)
-            && steps >= min_steps
-            && steps <= max_steps
-        {
-            return Some(cost);
+}
+
+impl StateSpace {
+    fn size(&self, rows: usize) -> usize {
+        rows * self.cols * DIRECTIONS.len() * (self.max_steps + 1)
+    }
+
+    fn encode(&self, position: (usize, usize), direction: usize, steps: usize) -> usize {
+        ((position.0 * self.cols + position.1) * DIRECTIONS.len() + direction)
+            * (self.max_steps + 1)
+            + steps
+    }
+
+    fn decode(&self, index: usize) -> ((usize, usize), usize, usize) {
+        let steps = index % (self.max_steps + 1);
+        let rest = index / (self.max_steps + 1);
+        let direction = rest % DIRECTIONS.len();
+        let cell = rest / DIRECTIONS.len();
+        ((cell / self.cols, cell % self.cols), direction, steps)
+    }
+}
+
+/// Returns the cheapest route from `constraints.start` to `constraints.goal`,
+/// or None if the goal cannot be reached.
+pub fn shortest_route(grid: &[Vec<usize>], constraints: &Constraints) -> Option<Route> {
+    let (rows, cols) = (grid.len(), grid[0].len());
+    let Constraints {
+        start,
+        goal,
+        min_steps,
+        max_steps,
+        a_star,
+    } = *constraints;
+    let space = StateSpace { cols, max_steps };
+
+    let cheapest_cell = grid.iter().flatten().copied().min().unwrap_or(0);
+    let heuristic = |position: (usize, usize)| {
+        if a_star {
+            (position.0.abs_diff(goal.0) + position.1.abs_diff(goal.1)) * cheapest_cell
+        } else {
+            0
         }
+    };
+
+    let mut best = vec![usize::MAX; space.size(rows)];
+    let mut previous = vec![usize::MAX; space.size(rows)];
+    let mut queue = BinaryHeap::new();
+
+    // The crucible has not moved yet, so it may leave the start in any direction.
+    // Seeding a zero-step state per heading lets the first move count as a fresh run.
+    for direction in 0..DIRECTIONS.len() {
+        let initial = space.encode(start, direction, 0);
+        best[initial] = 0;
+        queue.push(State {
+            priority: heuristic(start),
+            cost: 0,
+            index: initial,
+        });
+    }
 
-        for (_i, unit_vector) in direction_unit_vectors.iter().enumerate() {
-            let new_direction = *unit_vector;
+    while let Some(State { cost, index, .. }) = queue.pop() {
+        if cost > best[index] {
+            continue;
+        }
+        let (position, direction, steps) = space.decode(index);
+        if position == goal && steps >= min_steps {
+            return Some(Route {
+                cost,
+                path: reconstruct_path(&space, &previous, index),
+            });
+        }
 
+        for (new_direction, unit_vector) in DIRECTIONS.iter().enumerate() {
             // don't go back the way we came
-            if new_direction == (-direction.0, -direction.1) {
+            if new_direction == (direction + 2) % 4 {
                 continue;
             }
 
@@ -95,14 +164,13 @@ fn dijkstras_with_step_bound(
 
             // don't go out of bounds
             let new_position = (
-                position.0 as i32 + new_direction.0,
-                position.1 as i32 + new_direction.1,
+                position.0 as i32 + unit_vector.0,
+                position.1 as i32 + unit_vector.1,
             );
-
             if new_position.0 < 0
                 || new_position.1 < 0
-                || new_position.0 >= grid.len() as i32
-                || new_position.1 >= grid[0].len() as i32
+                || new_position.0 >= rows as i32
+                || new_position.1 >= cols as i32
             {
                 continue;
             }
@@ -114,19 +182,75 @@ fn dijkstras_with_step_bound(
             } else {
                 1
             };
-            // factor cost
-            if visited.contains(&(new_position, new_direction, new_steps)) {
+            let new_index = space.encode(new_position, new_direction, new_steps);
+            if new_cost >= best[new_index] {
                 continue;
             }
 
-            visited.insert((new_position, new_direction, new_steps));
-            queue.push(State::new(new_cost, new_position, new_direction, new_steps));
+            best[new_index] = new_cost;
+            previous[new_index] = index;
+            queue.push(State {
+                priority: new_cost + heuristic(new_position),
+                cost: new_cost,
+                index: new_index,
+            });
         }
     }
     None
 }
 
-fn parse_input(input: &str) -> Vec<Vec<usize>> {
+fn reconstruct_path(space: &StateSpace, previous: &[usize], end: usize) -> Vec<(usize, usize)> {
+    let mut path = vec![];
+    let mut index = end;
+    loop {
+        path.push(space.decode(index).0);
+        if previous[index] == usize::MAX {
+            break;
+        }
+        index = previous[index];
+    }
+    path.reverse();
+    path
+}
+
+/// Draws the route over the grid the way the puzzle does, with an arrow
+/// on every cell the crucible moves into.
+pub fn render_route(grid: &[Vec<usize>], route: &Route) -> String {
+    let mut canvas = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&cell| char::from_digit(cell as u32, 10).unwrap_or('?'))
+                .collect_vec()
+        })
+        .collect_vec();
+    for (from, to) in route.path.iter().tuple_windows() {
+        canvas[to.0][to.1] = match (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32) {
+            (-1, 0) => '^',
+            (1, 0) => 'v',
+            (0, -1) => '<',
+            _ => '>',
+        };
+    }
+    canvas
+        .iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+/// Returns the cost of the cheapest path from the top-left to the bottom-right
+/// of the grid, moving between `min_steps` and `max_steps` (inclusive)
+/// in a straight line at a time.
+/// If no path is found, returns None.
+fn dijkstras_with_step_bound(
+    grid: &[Vec<usize>],
+    min_steps: usize,
+    max_steps: usize,
+) -> Option<usize> {
+    shortest_route(grid, &Constraints::new(grid, min_steps, max_steps)).map(|route| route.cost)
+}
+
+pub fn parse_input(input: &str) -> Vec<Vec<usize>> {
     input
         .lines()
         .map(|line| {
@@ -140,6 +264,8 @@ fn parse_input(input: &str) -> Vec<Vec<usize>> {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn solves_17_1_easy() {
         let input = std::fs::read_to_string("input/17_easy.txt").unwrap();
@@ -164,6 +290,159 @@ mod tests {
         assert_eq!(result, Some(71));
     }
 
+    #[test]
+    fn renders_route_and_agrees_with_a_star() {
+        let inp = "111111111111
+        999999999991
+        999999999991
+        999999999991
+        999999999991";
+        let grid = parse_input(inp);
+        let mut constraints = Constraints::new(&grid, 4, 10);
+        let route = shortest_route(&grid, &constraints).unwrap();
+        assert_eq!(route.path.first(), Some(&(0, 0)));
+        assert_eq!(route.path.last(), Some(&(4, 11)));
+        assert_eq!(
+            render_route(&grid, &route),
+            "1>>>>>>>1111
+9999999v9991
+9999999v9991
+9999999v9991
+9999999v>>>>"
+        );
+        constraints.a_star = true;
+        assert_eq!(shortest_route(&grid, &constraints).unwrap().cost, 71);
+    }
+
+    #[test]
+    fn routes_between_arbitrary_cells() {
+        let input = std::fs::read_to_string("input/17_easy.txt").unwrap();
+        let grid = parse_input(&input);
+        let constraints = Constraints {
+            start: (2, 2),
+            goal: (5, 3),
+            min_steps: 0,
+            max_steps: 3,
+            a_star: true,
+        };
+        let route = shortest_route(&grid, &constraints).unwrap();
+        let cost: usize = route.path[1..].iter().map(|&(r, c)| grid[r][c]).sum();
+        assert_eq!(route.cost, cost);
+        assert_eq!(
+            (route.path[0], *route.path.last().unwrap()),
+            ((2, 2), (5, 3))
+        );
+    }
+
+    /// Grids with an expensive first row and a cheap first column, so the
+    /// cheapest route has to leave the start heading south.
+    fn south_first_grids() -> Vec<Vec<Vec<usize>>> {
+        let mut seed: u64 = 17;
+        let mut next_digit = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % 9 + 1
+        };
+        (0..12)
+            .map(|i| {
+                let (rows, cols) = (6 + i % 3, 6 + i / 4);
+                let mut grid = vec![vec![0; cols]; rows];
+                for (r, row) in grid.iter_mut().enumerate() {
+                    for (c, cell) in row.iter_mut().enumerate() {
+                        *cell = match (r, c) {
+                            (0, _) => 9,
+                            (_, 0) => 1,
+                            _ => next_digit(),
+                        };
+                    }
+                }
+                grid
+            })
+            .collect_vec()
+    }
+
+    /// Relaxes every move from every state until nothing changes.
+    fn brute_force(
+        grid: &[Vec<usize>],
+        min_steps: usize,
+        max_steps: usize,
+        headings: &[usize],
+    ) -> Option<usize> {
+        let (rows, cols) = (grid.len(), grid[0].len());
+        let mut best = vec![vec![vec![vec![usize::MAX; max_steps + 1]; 4]; cols]; rows];
+        for &heading in headings {
+            best[0][0][heading][0] = 0;
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for r in 0..rows {
+                for c in 0..cols {
+                    for direction in 0..4 {
+                        for steps in 0..=max_steps {
+                            let cost = best[r][c][direction][steps];
+                            if cost == usize::MAX {
+                                continue;
+                            }
+                            for (new_direction, (dr, dc)) in DIRECTIONS.iter().enumerate() {
+                                let turning = new_direction != direction;
+                                if new_direction == (direction + 2) % 4
+                                    || (turning && steps < min_steps)
+                                    || (!turning && steps == max_steps)
+                                {
+                                    continue;
+                                }
+                                let (nr, nc) = (r as i32 + dr, c as i32 + dc);
+                                if nr < 0 || nc < 0 || nr >= rows as i32 || nc >= cols as i32 {
+                                    continue;
+                                }
+                                let (nr, nc) = (nr as usize, nc as usize);
+                                let new_steps = if turning { 1 } else { steps + 1 };
+                                let new_cost = cost + grid[nr][nc];
+                                if new_cost < best[nr][nc][new_direction][new_steps] {
+                                    best[nr][nc][new_direction][new_steps] = new_cost;
+                                    changed = true;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        best[rows - 1][cols - 1]
+            .iter()
+            .flat_map(|by_steps| by_steps[min_steps..].iter())
+            .copied()
+            .min()
+            .filter(|&cost| cost != usize::MAX)
+    }
+
+    #[test]
+    fn best_route_may_start_southward() {
+        for grid in south_first_grids() {
+            for (min_steps, max_steps) in [(1, 3), (4, 10)] {
+                let expected = brute_force(&grid, min_steps, max_steps, &[0, 1, 2, 3]);
+                let east_only = brute_force(&grid, min_steps, max_steps, &[1]);
+                assert!(
+                    expected < east_only,
+                    "grid {:?} does not favour south",
+                    grid
+                );
+
+                for a_star in [false, true] {
+                    let constraints = Constraints {
+                        a_star,
+                        ..Constraints::new(&grid, min_steps, max_steps)
+                    };
+                    let route = shortest_route(&grid, &constraints).unwrap();
+                    assert_eq!(Some(route.cost), expected, "grid {:?}", grid);
+                    assert_eq!(route.path[1], (1, 0));
+                }
+            }
+        }
+    }
+
     #[test]
     fn solves_17_2_easy() {
         let input = std::fs::read_to_string("input/17_easy.txt").unwrap();
@@ -173,6 +452,6 @@ mod tests {
     #[test]
     fn solves_17_2_hard() {
         let input = std::fs::read_to_string("input/17_real.txt").unwrap();
-        assert_eq!(super::solve_part_two(&input), 1256);
+        assert_eq!(super::solve_part_two(&input), 1249);
     }
 }
@@ -1,55 +1,84 @@
 use itertools::Itertools;
+use num::integer::Roots;
 
 pub fn solve_part_one(input: &str) -> u64 {
-    let (times, dist) = parse_input(input);
-    let mut prod = 1;
-    for i in 0..dist.len() {
-        let time = times[i];
-        let dist = dist[i];
-        let mut numbers = 0;
-        for x in 0..=time {
-            let dx = x * (time - x);
-            if dx > dist {
-                numbers += 1;
-            }
-        }
-        //println!("numbers: {:?}", numbers);
-        prod *= numbers;
-    }
-    prod
+    parse_races(input, ColumnMode::Separate)
+        .iter()
+        .map(Race::winning_hold_times)
+        .product::<u128>()
+        .try_into()
+        .unwrap()
 }
 
 pub fn solve_part_two(input: &str) -> u64 {
-    let (times, dist) = parse_input(input);
-    let times_string = times.iter().map(|x| x.to_string()).join("");
-    let dist_string = dist.iter().map(|x| x.to_string()).join("");
-    let times = times_string.parse::<u64>().unwrap();
-    let dist = dist_string.parse::<u64>().unwrap();
-    let mut prod = 1;
-    let mut numbers = 0;
-    for x in 0..=times {
-        let dx = x * (times - x);
-        if dx > dist {
-            numbers += 1;
+    parse_races(input, ColumnMode::Concatenate)
+        .iter()
+        .map(Race::winning_hold_times)
+        .product::<u128>()
+        .try_into()
+        .unwrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Race {
+    pub time: u128,
+    pub distance: u128,
+}
+
+impl Race {
+    /// Counts the hold times `x` with `x * (time - x) > distance`.
+    /// The boat beats the record strictly between the roots of
+    /// `x^2 - time * x + distance`, so the count comes from the integer
+    /// square root of the discriminant, nudged to the first winning hold time.
+    pub fn winning_hold_times(&self) -> u128 {
+        let Race { time, distance } = *self;
+        let discriminant = match (time * time).checked_sub(4 * distance) {
+            Some(discriminant) if discriminant > 0 => discriminant,
+            _ => return 0,
+        };
+        let beats = |x: u128| x * (time - x) > distance;
+        let mut lowest = (time - discriminant.sqrt()) / 2;
+        while lowest > 0 && beats(lowest - 1) {
+            lowest -= 1;
+        }
+        while lowest <= time / 2 && !beats(lowest) {
+            lowest += 1;
+        }
+        if lowest > time / 2 {
+            return 0;
         }
+        // the winning hold times are symmetric around time / 2
+        time - 2 * lowest + 1
     }
-    //println!("numbers: {:?}", numbers);
-    prod *= numbers;
-    prod
 }
 
-fn parse_input(input: &str) -> (Vec<u64>, Vec<u64>) {
-    let lines: Vec<&str> = input.lines().collect();
+/// How the numbers on each line make up races.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnMode {
+    /// Every column is its own race.
+    Separate,
+    /// All columns are the digits of a single race, ignoring the spaces.
+    Concatenate,
+}
 
-    let times: Vec<u64> = lines[0].split(':').collect_vec()[1]
-        .split_whitespace()
-        .map(|x| x.parse().unwrap())
-        .collect();
-    let dist: Vec<u64> = lines[1].split(':').collect_vec()[1]
-        .split_whitespace()
-        .map(|x| x.parse().unwrap())
-        .collect();
-    (times, dist)
+pub fn parse_races(input: &str, mode: ColumnMode) -> Vec<Race> {
+    let columns = |line: &str| {
+        let tokens = line.split(':').collect_vec()[1].split_whitespace();
+        let tokens = match mode {
+            ColumnMode::Separate => tokens.map(|x| x.to_string()).collect_vec(),
+            ColumnMode::Concatenate => vec![tokens.collect::<String>()],
+        };
+        tokens
+            .iter()
+            .map(|x| x.parse::<u128>().unwrap())
+            .collect_vec()
+    };
+    let lines: Vec<&str> = input.lines().collect();
+    columns(lines[0])
+        .into_iter()
+        .zip(columns(lines[1]))
+        .map(|(time, distance)| Race { time, distance })
+        .collect_vec()
 }
 
 #[cfg(test)]
@@ -58,11 +87,42 @@ mod tests {
     #[test]
     fn parses_simple_input() {
         let input = "Time:      7  15   30\nDistance:  9  40  200";
-        let (times, dist) = super::parse_input(input);
+        let races = super::parse_races(input, super::ColumnMode::Separate);
+        let times = races.iter().map(|r| r.time).collect::<Vec<_>>();
+        let dist = races.iter().map(|r| r.distance).collect::<Vec<_>>();
         assert_eq!(times, vec![7, 15, 30]);
         assert_eq!(dist, vec![9, 40, 200]);
     }
 
+    #[test]
+    fn counts_match_brute_force() {
+        for time in 0..60u128 {
+            for distance in 0..(time * time / 4 + 2) {
+                let race = super::Race { time, distance };
+                let brute = (0..=time).filter(|x| x * (time - x) > distance).count();
+                assert_eq!(race.winning_hold_times(), brute as u128);
+            }
+        }
+        let huge = super::Race {
+            time: 10u128.pow(18),
+            distance: 10u128.pow(30),
+        };
+        assert!(huge.winning_hold_times() > 0);
+    }
+
+    #[test]
+    fn concatenates_columns() {
+        let input = "Time:      7  15   30\nDistance:  9  40  200";
+        let races = super::parse_races(input, super::ColumnMode::Concatenate);
+        assert_eq!(
+            races,
+            vec![super::Race {
+                time: 71530,
+                distance: 940200
+            }]
+        );
+    }
+
     #[test]
     fn solves_6_1_easy() {
         let input = std::fs::read_to_string("input/6_easy.txt").unwrap();
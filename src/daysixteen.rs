@@ -1,100 +1,312 @@
-use std::{
-    cmp,
-    collections::{HashSet, VecDeque},
-};
-
 use itertools::Itertools;
 
+use crate::utils::Grid;
+
 type Position = (i32, i32);
 type Direction = (i32, i32);
 
-fn bfs(grid: &[Vec<char>], start_pos: Position, start_dir: Direction) -> usize {
-    let m = grid.len() as i32;
-    let n = grid[0].len() as i32;
-    let mut seen = HashSet::new();
-    let mut q = VecDeque::new();
-    q.push_back((start_pos, start_dir));
-    let mut visited_grid = vec![vec!['.'; n as usize]; m as usize];
-    while let Some(((i, j), (dx, dy))) = q.pop_front() {
-        let newx = i + dx;
-        let newy = j + dy;
-        if seen.contains(&(newx, newy, dx, dy)) || newx < 0 || newy < 0 || newx >= m || newy >= n {
-            continue;
+const DIRECTIONS: [Direction; 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+/// Directions a beam travelling in `(dx, dy)` leaves `tile` in.
+fn deflect(tile: char, (dx, dy): Direction) -> Vec<Direction> {
+    match tile {
+        '/' => vec![(-dy, -dx)],
+        '\\' => vec![(dy, dx)],
+        '|' if dy != 0 => vec![(1, 0), (-1, 0)],
+        '-' if dx != 0 => vec![(0, 1), (0, -1)],
+        _ => vec![(dx, dy)],
+    }
+}
+
+fn direction_index(direction: Direction) -> usize {
+    DIRECTIONS.iter().position(|&d| d == direction).unwrap()
+}
+
+/// Precomputed beam graph of a contraption.
+///
+/// Every node is a mirror or splitter together with the direction a beam leaves it in.
+/// A node owns the straight run of cells up to the next mirror or splitter, and links
+/// to the nodes the beam turns into there. The strongly connected components of that
+/// graph are condensed and each one stores the bitset of every cell reachable from it,
+/// so energising the grid from any entry point is a union of at most two bitsets.
+pub struct BeamEngine {
+    grid: Grid<char>,
+    rows: usize,
+    cols: usize,
+    /// SCC of every node, indexed by `(row * cols + col) * 4 + direction`.
+    component: Vec<usize>,
+    /// Energised cells reachable from each SCC, one bit per cell.
+    reach: Vec<Vec<u64>>,
+}
+
+impl BeamEngine {
+    pub fn new(grid: &[Vec<char>]) -> Self {
+        let rows = grid.len();
+        let cols = grid[0].len();
+        let mut engine = BeamEngine {
+            grid: grid.to_vec(),
+            rows,
+            cols,
+            component: vec![usize::MAX; rows * cols * 4],
+            reach: vec![],
+        };
+
+        let mut cells = vec![vec![]; rows * cols * 4];
+        let mut edges = vec![vec![]; rows * cols * 4];
+        let mut nodes = vec![];
+        for (i, j) in (0..rows).cartesian_product(0..cols) {
+            if grid[i][j] == '.' {
+                continue;
+            }
+            for (d, &direction) in DIRECTIONS.iter().enumerate() {
+                let node = engine.node((i as i32, j as i32), d);
+                let start = (i as i32 + direction.0, j as i32 + direction.1);
+                let (run, next) = engine.walk(start, direction);
+                cells[node] = run;
+                cells[node].push(i * cols + j);
+                edges[node] = next;
+                nodes.push(node);
+            }
         }
-        seen.insert((newx, newy, dx, dy));
-        visited_grid[newx as usize][newy as usize] = '#';
-        let tile = grid[newx as usize][newy as usize];
-        let (new_dx, new_dy) = match tile {
-            '/' => (-dy, -dx),
-            '\\' => (dy, dx),
-            '|' => {
-                if dy != 0 {
-                    (1, 0)
-                } else {
-                    (dx, dy)
-                }
+
+        engine.condense(&nodes, &cells, &edges);
+        engine
+    }
+
+    fn node(&self, (i, j): Position, direction: usize) -> usize {
+        (i as usize * self.cols + j as usize) * 4 + direction
+    }
+
+    fn in_bounds(&self, (i, j): Position) -> bool {
+        i >= 0 && j >= 0 && i < self.rows as i32 && j < self.cols as i32
+    }
+
+    /// Follows a beam entering `start` in `direction` through empty cells.
+    /// Returns the cells it crosses and the nodes it turns into at the first
+    /// mirror or splitter, which is not itself included in the crossed cells.
+    fn walk(&self, start: Position, direction: Direction) -> (Vec<usize>, Vec<usize>) {
+        let mut run = vec![];
+        let mut position = start;
+        while self.in_bounds(position) {
+            let tile = self.grid[position.0 as usize][position.1 as usize];
+            if tile != '.' {
+                let next = deflect(tile, direction)
+                    .into_iter()
+                    .map(|out| self.node(position, direction_index(out)))
+                    .collect();
+                return (run, next);
             }
-            '-' => {
-                if dx != 0 {
-                    (0, 1)
-                } else {
-                    (dx, dy)
+            run.push(position.0 as usize * self.cols + position.1 as usize);
+            position = (position.0 + direction.0, position.1 + direction.1);
+        }
+        (run, vec![])
+    }
+
+    /// Tarjan's algorithm, run iteratively. Components come out in reverse
+    /// topological order, so every successor's bitset is ready before it is needed.
+    fn condense(&mut self, nodes: &[usize], cells: &[Vec<usize>], edges: &[Vec<usize>]) {
+        let words = (self.rows * self.cols).div_ceil(64);
+        let mut index = vec![usize::MAX; cells.len()];
+        let mut low = vec![0; cells.len()];
+        let mut on_stack = vec![false; cells.len()];
+        let mut stack = vec![];
+        let mut counter = 0;
+
+        for &root in nodes {
+            if index[root] != usize::MAX {
+                continue;
+            }
+            let mut call_stack = vec![(root, 0)];
+            index[root] = counter;
+            low[root] = counter;
+            counter += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some(&(node, next_edge)) = call_stack.last() {
+                if let Some(&successor) = edges[node].get(next_edge) {
+                    call_stack.last_mut().unwrap().1 += 1;
+                    if index[successor] == usize::MAX {
+                        index[successor] = counter;
+                        low[successor] = counter;
+                        counter += 1;
+                        stack.push(successor);
+                        on_stack[successor] = true;
+                        call_stack.push((successor, 0));
+                    } else if on_stack[successor] {
+                        low[node] = low[node].min(index[successor]);
+                    }
+                    continue;
+                }
+
+                call_stack.pop();
+                if let Some(&(parent, _)) = call_stack.last() {
+                    low[parent] = low[parent].min(low[node]);
+                }
+                if low[node] != index[node] {
+                    continue;
                 }
+
+                let id = self.reach.len();
+                let mut bits = vec![0u64; words];
+                let mut members = vec![];
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    self.component[member] = id;
+                    members.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                for &member in &members {
+                    for &cell in &cells[member] {
+                        bits[cell / 64] |= 1 << (cell % 64);
+                    }
+                    for &successor in &edges[member] {
+                        let other = self.component[successor];
+                        if other != id {
+                            bits.iter_mut()
+                                .zip(&self.reach[other])
+                                .for_each(|(a, b)| *a |= b);
+                        }
+                    }
+                }
+                self.reach.push(bits);
             }
-            _ => (dx, dy),
-        };
-        if tile == '|' && dy != 0 {
-            q.push_back(((newx, newy), (-1, 0)));
         }
-        if tile == '-' && dx != 0 {
-            q.push_back(((newx, newy), (0, -1)));
+    }
+
+    fn energised_bits(&self, entry: Position, direction: Direction) -> Vec<u64> {
+        let (run, next) = self.walk(entry, direction);
+        let mut bits = vec![0u64; (self.rows * self.cols).div_ceil(64)];
+        for cell in run {
+            bits[cell / 64] |= 1 << (cell % 64);
         }
-        q.push_back(((newx, newy), (new_dx, new_dy)));
-    }
-    visited_grid
-        .iter()
-        .for_each(|line| println!("{}", line.iter().collect::<String>()));
-    println!("");
-    let hash_count = visited_grid.iter().flatten().filter(|&&c| c == '#').count();
-    return hash_count;
+        for node in next {
+            bits.iter_mut()
+                .zip(&self.reach[self.component[node]])
+                .for_each(|(a, b)| *a |= b);
+        }
+        bits
+    }
+
+    /// The cells energised by a beam entering the grid at `entry`, which must be
+    /// a cell of the grid, travelling in `direction`.
+    pub fn energised(&self, entry: Position, direction: Direction) -> Grid<bool> {
+        let bits = self.energised_bits(entry, direction);
+        (0..self.rows)
+            .map(|i| {
+                (0..self.cols)
+                    .map(|j| {
+                        let cell = i * self.cols + j;
+                        bits[cell / 64] & (1 << (cell % 64)) != 0
+                    })
+                    .collect_vec()
+            })
+            .collect_vec()
+    }
+
+    pub fn energised_count(&self, entry: Position, direction: Direction) -> usize {
+        self.energised_bits(entry, direction)
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Every edge cell paired with the direction pointing into the grid.
+    pub fn entries(&self) -> Vec<(Position, Direction)> {
+        let (m, n) = (self.rows as i32, self.cols as i32);
+        let rows = (0..m).flat_map(|i| [((i, 0), (0, 1)), ((i, n - 1), (0, -1))]);
+        let cols = (0..n).flat_map(|j| [((0, j), (1, 0)), ((m - 1, j), (-1, 0))]);
+        rows.chain(cols).collect_vec()
+    }
+
+    /// The entry energising the most cells, as `(entry, direction, energised count)`.
+    pub fn best_entry(&self) -> (Position, Direction, usize) {
+        self.entries()
+            .into_iter()
+            .map(|(entry, direction)| (entry, direction, self.energised_count(entry, direction)))
+            .max_by_key(|&(_, _, count)| count)
+            .unwrap()
+    }
 }
 
-fn solve(data: Vec<String>) -> usize {
-    let grid: Vec<Vec<char>> = data.iter().map(|line| line.chars().collect()).collect();
-    let p1 = bfs(&grid, (0, -1), (0, 1));
-    return p1;
+fn parse_input(input: &str) -> Grid<char> {
+    input
+        .lines()
+        .map(|line| line.trim().chars().collect_vec())
+        .collect_vec()
 }
 
 pub fn solve_part_two(input: &str) -> usize {
-    let input = input.lines().map(|line| line.to_string()).collect_vec();
-    let input = input
-        .iter()
-        .map(|line| line.chars().collect_vec())
-        .collect_vec();
-    let m = input.len();
-    let n = input[0].len();
-    let mut res = 0;
-    for i in 0..m {
-        res = cmp::max(res, bfs(&input, (i.try_into().unwrap(), -1), (0, 1)));
-        res = cmp::max(res, bfs(&input, (i.try_into().unwrap(), n as i32), (0, -1)));
-    }
-    for j in 0..n {
-        res = cmp::max(res, bfs(&input, (-1, j.try_into().unwrap()), (1, 0)));
-        res = cmp::max(res, bfs(&input, (m as i32, j.try_into().unwrap()), (-1, 0)));
-    }
-    return res;
+    let engine = BeamEngine::new(&parse_input(input));
+    engine.best_entry().2
 }
 
 pub fn solve_part_one(input: &str) -> usize {
-    let input = input.lines().map(|line| line.to_string()).collect();
-    solve(input)
+    let engine = BeamEngine::new(&parse_input(input));
+    engine.energised_count((0, 0), (0, 1))
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::collections::{HashSet, VecDeque};
+
     use super::*;
 
+    /// Straightforward beam simulation used as a reference for the engine.
+    fn bfs(grid: &[Vec<char>], start_pos: Position, start_dir: Direction) -> Grid<bool> {
+        let m = grid.len() as i32;
+        let n = grid[0].len() as i32;
+        let mut seen = HashSet::new();
+        let mut q = VecDeque::new();
+        q.push_back((start_pos, start_dir));
+        let mut visited_grid = vec![vec![false; n as usize]; m as usize];
+        while let Some(((i, j), (dx, dy))) = q.pop_front() {
+            let newx = i + dx;
+            let newy = j + dy;
+            if seen.contains(&(newx, newy, dx, dy))
+                || newx < 0
+                || newy < 0
+                || newx >= m
+                || newy >= n
+            {
+                continue;
+            }
+            seen.insert((newx, newy, dx, dy));
+            visited_grid[newx as usize][newy as usize] = true;
+            for direction in deflect(grid[newx as usize][newy as usize], (dx, dy)) {
+                q.push_back(((newx, newy), direction));
+            }
+        }
+        visited_grid
+    }
+
+    #[test]
+    fn matches_reference_simulation_for_every_entry() {
+        let input = std::fs::read_to_string("input/16_real.txt").unwrap();
+        let grid = parse_input(&input);
+        let engine = BeamEngine::new(&grid);
+        for (entry, direction) in engine.entries() {
+            let outside = (entry.0 - direction.0, entry.1 - direction.1);
+            assert_eq!(
+                engine.energised(entry, direction),
+                bfs(&grid, outside, direction),
+                "entry {:?} heading {:?}",
+                entry,
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn finds_best_entry() {
+        let input = std::fs::read_to_string("input/16_easy.txt").unwrap();
+        let engine = BeamEngine::new(&parse_input(&input));
+        assert_eq!(engine.best_entry(), ((0, 3), (1, 0), 51));
+    }
+
     #[test]
     fn solves_16_1_easy() {
         let input = std::fs::read_to_string("input/16_easy.txt").unwrap();
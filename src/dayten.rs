@@ -1,152 +1,369 @@
+use std::str::FromStr;
+
 use itertools::Itertools;
-use std::collections::HashMap;
 
-/// Direction is one of the four possible directions that you can travel in the maze from the current position.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum Direction {
+use crate::utils::Grid;
+
+type Position = (usize, usize);
+
+/// One of the four ways a pipe can lead out of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
     North,
     East,
     South,
     West,
-    Anywhere,
 }
 
-// Returns the mapping from position to the two directions that you can travel from that position.
-fn parse_part_one(
-    input: &str,
-) -> (
-    (usize, usize),
-    HashMap<(usize, usize), Vec<Direction>>,
-    Vec<Vec<char>>,
-) {
-    let mut mapping = HashMap::new();
-    let raw_input: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
-    let mut start = (0, 0);
-    for i in 0..raw_input.len() {
-        for j in 0..(raw_input[0]).len() {
-            let c = raw_input[i][j];
-            let pair = match c {
-                '|' => ((i, j), vec![Direction::North, Direction::South]),
-                '-' => ((i, j), vec![Direction::East, Direction::West]),
-                'L' => ((i, j), vec![Direction::North, Direction::East]),
-                'J' => ((i, j), vec![Direction::North, Direction::West]),
-                '7' => ((i, j), vec![Direction::South, Direction::West]),
-                'F' => ((i, j), vec![Direction::South, Direction::East]),
-                '.' => ((i, j), vec![]),
-                'S' => {
-                    start = (i, j);
-                    ((i, j), vec![Direction::Anywhere])
-                }
-                _ => panic!("Invalid character in input: {}", c),
-            };
-            mapping.insert(pair.0, pair.1);
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+impl Direction {
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
         }
     }
-    (start, mapping, raw_input)
 }
 
-// Assign the start pipe one of the four directions.
-fn assign_start(
-    start: (usize, usize),
-    bounds: (usize, usize),
-    mapping: &mut HashMap<(usize, usize), Vec<Direction>>,
-) {
-    let (startx, starty) = start;
-    let mut possible_directions = vec![];
-
-    // check above me
-    if startx > 0
-        && mapping
-            .get(&(startx - 1, starty))
-            .unwrap()
-            .contains(&Direction::South)
-    {
-        possible_directions.push(Direction::North);
-    }
-    // check below me
-    if startx < bounds.0 - 1
-        && mapping
-            .get(&(startx + 1, starty))
-            .unwrap()
-            .contains(&Direction::North)
-    {
-        possible_directions.push(Direction::South);
-    }
-    // Check the one on my left
-    if starty > 0
-        && mapping
-            .get(&(startx, starty - 1))
-            .unwrap()
-            .contains(&Direction::East)
-    {
-        // could have come west to me
-        possible_directions.push(Direction::West);
-    }
-    // check on my right
-    if starty < bounds.1 - 1
-        && mapping
-            .get(&(startx, starty + 1))
-            .unwrap()
-            .contains(&Direction::West)
-    {
-        possible_directions.push(Direction::East);
-    }
-    mapping.insert(start, possible_directions);
+/// The two directions a pipe connects, or `None` for anything that isn't a pipe.
+fn connections(pipe: char) -> Option<[Direction; 2]> {
+    match pipe {
+        '|' => Some([Direction::North, Direction::South]),
+        '-' => Some([Direction::East, Direction::West]),
+        'L' => Some([Direction::North, Direction::East]),
+        'J' => Some([Direction::North, Direction::West]),
+        '7' => Some([Direction::South, Direction::West]),
+        'F' => Some([Direction::East, Direction::South]),
+        _ => None,
+    }
+}
+
+fn neighbour(grid: &[Vec<char>], (row, col): Position, d: Direction) -> Option<Position> {
+    let next = match d {
+        Direction::North => (row.checked_sub(1)?, col),
+        Direction::East => (row, col + 1),
+        Direction::South => (row + 1, col),
+        Direction::West => (row, col.checked_sub(1)?),
+    };
+    grid.get(next.0)?.get(next.1)?;
+    Some(next)
 }
 
-pub fn solve_part_one(input: &str) -> u32 {
-    // I just need to simulate the run
-    let (start, mut mapping, graph) = parse_part_one(input);
-    let bounds = (graph.len(), graph[0].len());
-    assign_start(start, bounds, &mut mapping);
-    //println!("the mapping is {:?}", mapping.get(&start).unwrap());
-    let (mut curx, mut cury) = start;
-    let mut path = vec![];
-    let mut last_direction = Direction::Anywhere;
-    let dirs = vec![
-        Direction::North,
-        Direction::East,
-        Direction::South,
-        Direction::West,
-    ];
-    loop {
-        let destination = *dirs
-            .iter()
-            .filter(|d| *d != &last_direction && mapping.get(&(curx, cury)).unwrap().contains(d))
-            .collect_vec()
-            .first()
-            .expect("There should be at least one direction to go in");
-        match destination {
-            Direction::North => curx -= 1,
-            Direction::South => curx += 1,
-            Direction::East => cury += 1,
-            Direction::West => cury -= 1,
-            _ => panic!("Invalid direction"),
+/// The pipe connecting `a` and `b`.
+fn pipe_between(a: Direction, b: Direction) -> char {
+    "|-LJ7F"
+        .chars()
+        .find(|&pipe| {
+            let [x, y] = connections(pipe).unwrap();
+            (x, y) == (a, b) || (x, y) == (b, a)
+        })
+        .unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MazeError {
+    InvalidTile {
+        row: usize,
+        col: usize,
+        tile: char,
+    },
+    NoStart,
+    MultipleStarts,
+    /// No two of the pipes around `S` lead back to it around a loop.
+    NoLoop,
+}
+
+impl std::fmt::Display for MazeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MazeError::InvalidTile { row, col, tile } => {
+                write!(f, "invalid tile {:?} at row {}, column {}", tile, row, col)
+            }
+            MazeError::NoStart => write!(f, "the maze has no S"),
+            MazeError::MultipleStarts => write!(f, "the maze has more than one S"),
+            MazeError::NoLoop => write!(f, "S is not part of a loop"),
         }
-        path.push(((curx, cury), destination.clone()));
-        if (curx, cury) == start {
-            break;
+    }
+}
+
+impl std::error::Error for MazeError {}
+
+/// A pipe that isn't part of the main loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Defect {
+    /// At least one end of the pipe leads off the grid or into a tile that
+    /// doesn't connect back.
+    DeadEnd(Position),
+    /// Both ends are connected, but to pipes away from the main loop.
+    Disconnected(Position),
+}
+
+/// A field of pipes, with `S` replaced by the pipe it hides.
+pub struct PipeMaze {
+    grid: Grid<char>,
+    start: Position,
+    /// Tiles of the main loop in walking order, starting at `S`.
+    main_loop: Vec<Position>,
+    on_loop: Grid<bool>,
+}
+
+impl FromStr for PipeMaze {
+    type Err = MazeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut grid = s.lines().map(|l| l.chars().collect_vec()).collect_vec();
+        let mut start = None;
+        for (row, tiles) in grid.iter().enumerate() {
+            for (col, &tile) in tiles.iter().enumerate() {
+                match tile {
+                    'S' if start.is_some() => return Err(MazeError::MultipleStarts),
+                    'S' => start = Some((row, col)),
+                    '.' => {}
+                    _ if connections(tile).is_some() => {}
+                    _ => return Err(MazeError::InvalidTile { row, col, tile }),
+                }
+            }
         }
-        last_direction = match destination {
-            Direction::North => Direction::South,
-            Direction::East => Direction::West,
-            Direction::South => Direction::North,
-            Direction::West => Direction::East,
-            _ => panic!("Invalid direction"),
+        let start = start.ok_or(MazeError::NoStart)?;
+
+        let mut maze = PipeMaze {
+            on_loop: grid.iter().map(|row| vec![false; row.len()]).collect(),
+            grid: vec![],
+            start,
+            main_loop: vec![],
         };
+        // S could hide any pipe whose ends both lead into pipes pointing back at it
+        let open = DIRECTIONS
+            .into_iter()
+            .filter(|&d| {
+                neighbour(&grid, start, d)
+                    .and_then(|next| connections(grid[next.0][next.1]))
+                    .is_some_and(|ends| ends.contains(&d.opposite()))
+            })
+            .collect_vec();
+        for (a, b) in open.into_iter().tuple_combinations() {
+            grid[start.0][start.1] = pipe_between(a, b);
+            if let Some(main_loop) = maze.walk(&grid, a, b) {
+                for &(row, col) in &main_loop {
+                    maze.on_loop[row][col] = true;
+                }
+                maze.main_loop = main_loop;
+                maze.grid = grid;
+                return Ok(maze);
+            }
+        }
+        Err(MazeError::NoLoop)
+    }
+}
+
+impl PipeMaze {
+    /// Follows the pipes from `S`, leaving it heading `first`, until we are back at `S`.
+    /// The walk only counts if it comes back in through `last`, the other end of the
+    /// pipe we are trying under `S`.
+    fn walk(&self, grid: &[Vec<char>], first: Direction, last: Direction) -> Option<Vec<Position>> {
+        let mut path = vec![self.start];
+        let mut heading = first;
+        loop {
+            let position = neighbour(grid, *path.last().unwrap(), heading)?;
+            if position == self.start {
+                return (heading.opposite() == last).then_some(path);
+            }
+            let [a, b] = connections(grid[position.0][position.1])?;
+            heading = match heading.opposite() {
+                from if from == a => b,
+                from if from == b => a,
+                _ => return None,
+            };
+            path.push(position);
+        }
+    }
+
+    /// The maze with `S` replaced by its pipe.
+    pub fn grid(&self) -> &Grid<char> {
+        &self.grid
     }
-    //println!("{:?}", path);
-    path.len() as u32 / 2
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    /// The pipe hiding under `S`.
+    pub fn start_pipe(&self) -> char {
+        self.grid[self.start.0][self.start.1]
+    }
+
+    /// Every tile of the main loop in walking order, starting at `S`.
+    pub fn main_loop(&self) -> &[Position] {
+        &self.main_loop
+    }
+
+    /// The corners of the main loop in walking order, which is the loop as a polygon.
+    pub fn polygon(&self) -> Vec<Position> {
+        self.main_loop
+            .iter()
+            .copied()
+            .filter(|&(row, col)| !matches!(self.grid[row][col], '|' | '-'))
+            .collect()
+    }
+
+    /// Steps to the tile of the loop farthest from `S`.
+    pub fn farthest(&self) -> usize {
+        self.main_loop.len() / 2
+    }
+
+    /// Tiles inside the main loop. Scanning each row from the left, we are inside
+    /// after crossing the loop an odd number of times, where `|`, `L` and `J` count
+    /// as crossings and `-`, `7` and `F` don't.
+    pub fn enclosed(&self) -> Vec<Position> {
+        let mut enclosed = vec![];
+        for (row, tiles) in self.grid.iter().enumerate() {
+            let mut inside = false;
+            for (col, &tile) in tiles.iter().enumerate() {
+                if self.on_loop[row][col] {
+                    inside ^= matches!(tile, '|' | 'L' | 'J');
+                } else if inside {
+                    enclosed.push((row, col));
+                }
+            }
+        }
+        enclosed
+    }
+
+    /// Every pipe off the main loop, with what is wrong with it.
+    pub fn defects(&self) -> Vec<Defect> {
+        let mut defects = vec![];
+        for (row, tiles) in self.grid.iter().enumerate() {
+            for (col, &tile) in tiles.iter().enumerate() {
+                let Some(ends) = connections(tile) else {
+                    continue;
+                };
+                if self.on_loop[row][col] {
+                    continue;
+                }
+                let connected = ends.iter().all(|&d| {
+                    neighbour(&self.grid, (row, col), d)
+                        .and_then(|(r, c)| connections(self.grid[r][c]))
+                        .is_some_and(|back| back.contains(&d.opposite()))
+                });
+                defects.push(if connected {
+                    Defect::Disconnected((row, col))
+                } else {
+                    Defect::DeadEnd((row, col))
+                });
+            }
+        }
+        defects
+    }
+
+    /// The maze in box-drawing characters. The main loop is drawn heavy, other
+    /// pipes light, tiles enclosed by the loop shaded and the rest of the ground blank.
+    pub fn render(&self) -> String {
+        let mut enclosed = self
+            .grid
+            .iter()
+            .map(|row| vec![false; row.len()])
+            .collect_vec();
+        for (row, col) in self.enclosed() {
+            enclosed[row][col] = true;
+        }
+        self.grid
+            .iter()
+            .enumerate()
+            .map(|(row, tiles)| {
+                tiles
+                    .iter()
+                    .enumerate()
+                    .map(|(col, &tile)| {
+                        if enclosed[row][col] {
+                            return '▒';
+                        }
+                        let (light, heavy) = match tile {
+                            '|' => ('│', '┃'),
+                            '-' => ('─', '━'),
+                            'L' => ('└', '┗'),
+                            'J' => ('┘', '┛'),
+                            '7' => ('┐', '┓'),
+                            'F' => ('┌', '┏'),
+                            _ => (' ', ' '),
+                        };
+                        if self.on_loop[row][col] {
+                            heavy
+                        } else {
+                            light
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+}
+
+pub fn solve_part_one(input: &str) -> u32 {
+    let maze = PipeMaze::from_str(input).unwrap();
+    maze.farthest() as u32
 }
 
 pub fn solve_part_two(_input: &str) -> u32 {
     42
 }
 
-/// Parses the input into a list of positions and their entrace/exit pairs.
-
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_start_and_extracts_loop() {
+        let input = std::fs::read_to_string("input/10_easy.txt").unwrap();
+        let maze = PipeMaze::from_str(&input).unwrap();
+        assert_eq!(maze.start(), (1, 1));
+        assert_eq!(maze.start_pipe(), 'F');
+        assert_eq!(maze.main_loop().len(), 8);
+        assert_eq!(maze.polygon(), vec![(1, 1), (1, 3), (3, 3), (3, 1)]);
+        assert_eq!(maze.enclosed(), vec![(2, 2)]);
+        assert_eq!(maze.render(), "─└│┌┐\n┐┏━┓│\n└┃▒┃│\n─┗━┛│\n└│─┘┌");
+    }
+
+    #[test]
+    fn finds_defects() {
+        let maze = PipeMaze::from_str("S7.\n||-\nLJ.").unwrap();
+        assert_eq!(maze.defects(), vec![Defect::DeadEnd((1, 2))]);
+        let maze = PipeMaze::from_str("S7F7\nLJLJ").unwrap();
+        assert_eq!(maze.defects().len(), 4);
+        assert!(maze
+            .defects()
+            .iter()
+            .all(|d| matches!(d, Defect::Disconnected(_))));
+        assert_eq!(
+            PipeMaze::from_str("S-.\n|..").err(),
+            Some(MazeError::NoLoop)
+        );
+        assert_eq!(PipeMaze::from_str("..\n..").err(), Some(MazeError::NoStart));
+    }
+
+    #[test]
+    fn picks_the_pair_around_start_that_closes() {
+        // every pipe around S points at it, but only the east and south ones close a loop
+        let maze = PipeMaze::from_str(".|..\n-S7.\n.LJ.").unwrap();
+        assert_eq!(maze.start_pipe(), 'F');
+        assert_eq!(maze.farthest(), 2);
+    }
+
+    #[test]
+    fn resolves_start_on_a_straight_run() {
+        // the F reading also leaves east onto the loop, but comes back in from the west
+        let maze = PipeMaze::from_str(".....\nF-S-7\n|.|.|\nL---J").unwrap();
+        assert_eq!(maze.start_pipe(), '-');
+        assert_eq!(maze.render().lines().nth(1), Some("┏━━━┓"));
+    }
+
     #[test]
     fn solves_10_1_easy() {
         let input = std::fs::read_to_string("input/10_easy.txt").unwrap();
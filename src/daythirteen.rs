@@ -1,57 +1,243 @@
-use crate::utils::transpose;
+use crate::utils::{BitGrid, BitGridError};
 
 pub fn solve_part_one(input: &str) -> u64 {
-    input
-        .split("\n\n")
-        .map(|x| solve(x.to_string(), 0))
-        .sum::<usize>() as u64
+    summarize(input, 0).unwrap()
 }
 
 pub fn solve_part_two(input: &str) -> u64 {
+    summarize(input, 1).unwrap()
+}
+
+/// Adds up the reflection lines of every pattern that has exactly
+/// `smudges` cells out of place.
+pub fn summarize(input: &str, smudges: usize) -> Result<u64, MirrorError> {
     input
         .split("\n\n")
-        .map(|x| solve(x.to_string(), 2))
-        .sum::<usize>() as u64
+        .filter(|pattern| !pattern.trim().is_empty())
+        .enumerate()
+        .map(|(index, pattern)| {
+            find_reflection(&parse_pattern(index, pattern)?, smudges)
+                .map(|axis| axis.summary() as u64)
+                .ok_or(MirrorError::NoReflection { pattern: index })
+        })
+        .sum()
+}
+
+/// Parses the pattern with index `index`, which must be at most 64 cells each
+/// way and rectangular to fit in a `BitGrid`.
+fn parse_pattern(index: usize, pattern: &str) -> Result<BitGrid, MirrorError> {
+    let grid: Vec<Vec<char>> = pattern
+        .lines()
+        .map(|x| x.trim().chars().collect())
+        .collect();
+    BitGrid::from_grid(&grid, '#').map_err(|error| match error {
+        BitGridError::TooLarge => MirrorError::TooLarge { pattern: index },
+        BitGridError::Ragged => MirrorError::Ragged { pattern: index },
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// A line between two rows.
+    Horizontal,
+    /// A line between two columns.
+    Vertical,
+}
+
+/// A candidate reflection line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis {
+    pub orientation: Orientation,
+    /// The number of rows above, or columns to the left of, the line.
+    pub position: usize,
+    /// The number of mirrored cell pairs that differ.
+    pub mismatches: usize,
+    /// The `(row, col)` of one cell from every differing pair, on the side
+    /// above or left of the line. Flipping all of them makes the reflection exact.
+    pub smudges: Vec<(usize, usize)>,
 }
 
-fn solve(pattern: String, wiggle_room: usize) -> usize {
-    let pattern: Vec<Vec<char>> = pattern.lines().map(|x| x.chars().collect()).collect();
-    match reflect(pattern.clone(), wiggle_room) {
-        Some(row) => 100 * (row + 1),
-        None => match reflect(transpose(pattern), wiggle_room) {
-            Some(col) => col + 1,
-            None => 0,
-        },
+impl Axis {
+    pub fn summary(&self) -> usize {
+        match self.orientation {
+            Orientation::Horizontal => 100 * self.position,
+            Orientation::Vertical => self.position,
+        }
     }
 }
 
-fn reflect(pattern: Vec<Vec<char>>, wiggle_room: usize) -> Option<usize> {
-    let len = pattern.len();
-    (0..len - 1).find(|&i| {
-        let c = (0..len)
-            .filter_map(|j| {
-                let pos = i as i64 + 1 + (i as i64 - j as i64);
-                if pos < 0 || pos >= len as i64 {
-                    None
-                } else if pattern[j] != pattern[pos as usize] {
-                    Some(
-                        pattern[j]
-                            .iter()
-                            .zip(pattern[pos as usize].iter())
-                            .filter(|&(a, b)| a != b)
-                            .count(),
-                    )
-                } else {
-                    None
-                }
-            })
-            .sum::<usize>();
-        c == wiggle_room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MirrorError {
+    /// The pattern with this index has no line with the requested number of smudges.
+    NoReflection { pattern: usize },
+    /// The pattern with this index is over 64 cells across or tall.
+    TooLarge { pattern: usize },
+    /// The pattern with this index has rows of different lengths.
+    Ragged { pattern: usize },
+}
+
+impl std::fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorError::NoReflection { pattern } => {
+                write!(f, "pattern {} has no reflection line", pattern)
+            }
+            MirrorError::TooLarge { pattern } => {
+                write!(f, "pattern {} doesn't fit in a 64 by 64 grid", pattern)
+            }
+            MirrorError::Ragged { pattern } => {
+                write!(f, "pattern {} has rows of different lengths", pattern)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MirrorError {}
+
+/// Every horizontal and then every vertical line, with how far off a
+/// perfect reflection each one is.
+pub fn axes(pattern: &BitGrid) -> Vec<Axis> {
+    let mut axes = reflect(pattern.rows(), Orientation::Horizontal);
+    axes.extend(reflect(pattern.cols(), Orientation::Vertical));
+    axes
+}
+
+/// The first line with exactly `smudges` mismatches, horizontal lines first.
+pub fn find_reflection(pattern: &BitGrid, smudges: usize) -> Option<Axis> {
+    [
+        (pattern.rows(), Orientation::Horizontal),
+        (pattern.cols(), Orientation::Vertical),
+    ]
+    .into_iter()
+    .find_map(|(lines, orientation)| {
+        (1..lines.len())
+            .find(|&position| mismatches(lines, position, smudges) == smudges)
+            .map(|position| axis(lines, orientation, position))
     })
 }
 
+/// Counts the differing cells across the line before `lines[position]`,
+/// stopping early once there are more than `limit`.
+fn mismatches(lines: &[u64], position: usize, limit: usize) -> usize {
+    let mut count = 0;
+    for (above, below) in (0..position).rev().zip(position..lines.len()) {
+        count += (lines[above] ^ lines[below]).count_ones() as usize;
+        if count > limit {
+            break;
+        }
+    }
+    count
+}
+
+/// Checks every line between two of `lines`, which are the row masks for
+/// horizontal lines and the column masks for vertical ones.
+fn reflect(lines: &[u64], orientation: Orientation) -> Vec<Axis> {
+    (1..lines.len())
+        .map(|position| axis(lines, orientation, position))
+        .collect()
+}
+
+fn axis(lines: &[u64], orientation: Orientation, position: usize) -> Axis {
+    let mut smudges = vec![];
+    for (above, below) in (0..position).rev().zip(position..lines.len()) {
+        let mut difference = lines[above] ^ lines[below];
+        while difference != 0 {
+            let bit = difference.trailing_zeros() as usize;
+            smudges.push(match orientation {
+                Orientation::Horizontal => (above, bit),
+                Orientation::Vertical => (bit, above),
+            });
+            difference &= difference - 1;
+        }
+    }
+    smudges.sort();
+    Axis {
+        orientation,
+        position,
+        mismatches: smudges.len(),
+        smudges,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_axes_and_smudges() {
+        let input = std::fs::read_to_string("input/13_easy.txt").unwrap();
+        let patterns = input
+            .split("\n\n")
+            .enumerate()
+            .map(|(index, pattern)| parse_pattern(index, pattern).unwrap())
+            .collect::<Vec<_>>();
+
+        let exact = find_reflection(&patterns[0], 0).unwrap();
+        assert_eq!(
+            (exact.orientation, exact.position),
+            (Orientation::Vertical, 5)
+        );
+        let smudged = find_reflection(&patterns[0], 1).unwrap();
+        assert_eq!(
+            (smudged.orientation, smudged.position),
+            (Orientation::Horizontal, 3)
+        );
+        assert_eq!(smudged.smudges, vec![(0, 0)]);
+
+        let smudged = find_reflection(&patterns[1], 1).unwrap();
+        assert_eq!(smudged.position, 1);
+        assert_eq!(smudged.smudges, vec![(0, 4)]);
+        assert_eq!(axes(&patterns[1]).len(), 6 + 8);
+    }
+
+    #[test]
+    fn tolerates_any_number_of_smudges() {
+        let pattern = parse_pattern(0, "#..#\n....\n##.#\n.##.").unwrap();
+        let axis = find_reflection(&pattern, 3).unwrap();
+        assert_eq!(
+            (axis.orientation, axis.position),
+            (Orientation::Horizontal, 3)
+        );
+        assert_eq!(axis.smudges, vec![(2, 0), (2, 2), (2, 3)]);
+        assert_eq!(find_reflection(&pattern, 17), None);
+        for axis in axes(&pattern) {
+            let lines = match axis.orientation {
+                Orientation::Horizontal => pattern.rows(),
+                Orientation::Vertical => pattern.cols(),
+            };
+            assert_eq!(
+                mismatches(lines, axis.position, usize::MAX),
+                axis.mismatches
+            );
+        }
+    }
+
+    #[test]
+    fn reports_patterns_without_reflection() {
+        let input = "#.\n..\n\n##\n##";
+        assert_eq!(
+            summarize(input, 0),
+            Err(MirrorError::NoReflection { pattern: 0 })
+        );
+        assert_eq!(
+            summarize(input, 1).unwrap_err().to_string(),
+            "pattern 1 has no reflection line"
+        );
+        let wide = format!("##\n##\n\n{}", ".".repeat(65));
+        assert_eq!(
+            summarize(&wide, 0),
+            Err(MirrorError::TooLarge { pattern: 1 })
+        );
+        assert_eq!(
+            summarize("##\n##\n\n#.\n#", 0),
+            Err(MirrorError::Ragged { pattern: 1 })
+        );
+        assert_eq!(
+            summarize("##\n##\n\n#.\n#", 0).unwrap_err().to_string(),
+            "pattern 1 has rows of different lengths"
+        );
+    }
+
     #[test]
     fn solves_13_1_easy() {
         let input = std::fs::read_to_string("input/13_easy.txt").unwrap();
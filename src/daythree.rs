@@ -1,140 +1,231 @@
-use std::{
-    collections::{HashSet, VecDeque},
-    str::FromStr,
-};
+use std::{collections::HashMap, ops::Range, str::FromStr};
 
-pub struct Graph(Vec<Vec<char>>);
+/// A run of digits on a single row of the schematic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Number {
+    pub value: u32,
+    pub row: usize,
+    pub columns: Range<usize>,
+}
 
-impl FromStr for Graph {
-    type Err = ();
+/// Anything that is neither a digit nor a `.`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub symbol: char,
+    pub row: usize,
+    pub col: usize,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut graph = vec![];
-        for line in s.lines() {
-            let mut row = Vec::new();
-            for c in line.chars() {
-                row.push(c);
-            }
-            graph.push(row);
-        }
-        Ok(Graph(graph))
-    }
+/// The parsed engine schematic. A number and a symbol are adjacent when the
+/// symbol touches any digit of the number, diagonals included.
+pub struct Schematic {
+    numbers: Vec<Number>,
+    symbols: Vec<Symbol>,
+    /// Indices of the symbols adjacent to each number.
+    symbols_of: Vec<Vec<usize>>,
+    /// Indices of the numbers adjacent to each symbol.
+    numbers_of: Vec<Vec<usize>>,
 }
 
-impl Graph {
-    /// Returns "numbers" that are connected to the given position
-    pub fn search_numbers_from_position(&self, start: (usize, usize)) -> Vec<u32> {
-        let directions = [(0, 1),
-            (0, -1),
-            (1, 0),
-            (-1, 0),
-            (1, 1),
-            (-1, -1),
-            (1, -1),
-            (-1, 1)];
-        let mut queue = VecDeque::new();
-        let mut visited = HashSet::new();
+impl FromStr for Schematic {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut numbers = vec![];
-        queue.push_back(start);
-        while let Some((i, j)) = queue.pop_front() {
-            visited.insert((i, j));
-            for (dx, dy) in directions.iter() {
-                let nx = i as i32 + dx;
-                let ny = j as i32 + dy;
-                if nx < 0 || ny < 0 {
+        let mut symbols = vec![];
+        for (row, line) in s.lines().enumerate() {
+            // columns count chars, while slicing the line needs byte offsets
+            let mut start = None;
+            let chars = line
+                .char_indices()
+                .chain(std::iter::once((line.len(), '.')));
+            for (col, (offset, c)) in chars.enumerate() {
+                if c.is_ascii_digit() {
+                    start.get_or_insert((col, offset));
                     continue;
                 }
-                let nx = nx as usize;
-                let ny = ny as usize;
-                if ny >= self.0[0].len() || nx >= self.0.len() {
-                    continue;
+                if let Some((start_col, start_offset)) = start.take() {
+                    numbers.push(Number {
+                        value: line[start_offset..offset].parse().unwrap(),
+                        row,
+                        columns: start_col..col,
+                    });
                 }
-                let c = self.0[nx][ny];
-                if c.is_numeric() || (!c.is_numeric() && c != '.') {
-                    if visited.contains(&(nx, ny)) {
-                        continue;
-                    }
-                    queue.push_back((nx, ny));
+                if c != '.' && offset < line.len() {
+                    symbols.push(Symbol {
+                        symbol: c,
+                        row,
+                        col,
+                    });
                 }
             }
         }
-        for (i, row) in self.0.iter().enumerate() {
-            let mut current_number = String::new();
-            for (j, c) in row.iter().enumerate() {
-                if c.is_numeric() && visited.contains(&(i, j)) {
-                    current_number.push(*c);
-                } else if !current_number.is_empty() {
-                    numbers.push(current_number.parse::<u32>().unwrap());
-                    current_number = String::new();
+
+        let at = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| ((symbol.row, symbol.col), i))
+            .collect::<HashMap<_, _>>();
+        let mut numbers_of = vec![vec![]; symbols.len()];
+        let symbols_of = numbers
+            .iter()
+            .enumerate()
+            .map(|(n, number)| {
+                let rows = number.row.saturating_sub(1)..=number.row + 1;
+                let cols = number.columns.start.saturating_sub(1)..=number.columns.end;
+                let adjacent = rows
+                    .flat_map(|i| cols.clone().map(move |j| (i, j)))
+                    .filter_map(|position| at.get(&position).copied())
+                    .collect::<Vec<_>>();
+                for &symbol in &adjacent {
+                    numbers_of[symbol].push(n);
                 }
-            }
-            if !current_number.is_empty() {
-                numbers.push(current_number.parse::<u32>().unwrap());
-            }
-        }
-        numbers
+                adjacent
+            })
+            .collect();
+
+        Ok(Schematic {
+            numbers,
+            symbols,
+            symbols_of,
+            numbers_of,
+        })
     }
+}
 
-    fn get_symbol_pos(&self, symbol: char) -> Vec<(usize, usize)> {
-        let mut result = vec![];
-        for (i, row) in self.0.iter().enumerate() {
-            for (j, c) in row.iter().enumerate() {
-                if *c == symbol {
-                    result.push((i, j));
-                }
-            }
-        }
-        println!("the symbol {} is at {:?}", symbol, result);
-        result
+impl Schematic {
+    pub fn numbers(&self) -> &[Number] {
+        &self.numbers
     }
 
-    pub fn search(&self) -> Vec<u32> {
-        // we do a search from all symbols and mark any numbers we find as 'visited'
-        let mut symbols = vec![];
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
 
-        for (i, row) in self.0.iter().enumerate() {
-            for (j, c) in row.iter().enumerate() {
-                if *c != '.' && !c.is_numeric() {
-                    symbols.push((i, j));
-                }
-            }
-        }
+    /// The symbols touching `self.numbers()[number]`.
+    pub fn symbols_adjacent_to(&self, number: usize) -> impl Iterator<Item = &Symbol> {
+        self.symbols_of[number].iter().map(|&i| &self.symbols[i])
+    }
 
-        let mut connected_numbers = vec![];
-        for symbol_pos in symbols.into_iter() {
-            let found_numbers = self.search_numbers_from_position(symbol_pos);
-            connected_numbers.extend(found_numbers);
-        }
-        connected_numbers
+    /// The numbers touching `self.symbols()[symbol]`.
+    pub fn numbers_adjacent_to(&self, symbol: usize) -> impl Iterator<Item = &Number> {
+        self.numbers_of[symbol].iter().map(|&i| &self.numbers[i])
+    }
+
+    /// Numbers touching at least one symbol. Each is reported once, however
+    /// many symbols it touches.
+    pub fn part_numbers(&self) -> impl Iterator<Item = &Number> {
+        self.numbers_where(|symbols| !symbols.is_empty())
+    }
+
+    /// Numbers touching exactly `count` symbols of kind `symbol`.
+    pub fn numbers_touching(&self, symbol: char, count: usize) -> impl Iterator<Item = &Number> {
+        self.numbers_where(move |symbols| {
+            symbols.iter().filter(|s| s.symbol == symbol).count() == count
+        })
+    }
+
+    /// Numbers whose adjacent symbols satisfy `predicate`.
+    pub fn numbers_where<'a>(
+        &'a self,
+        predicate: impl Fn(&[Symbol]) -> bool + 'a,
+    ) -> impl Iterator<Item = &'a Number> {
+        self.numbers
+            .iter()
+            .enumerate()
+            .filter_map(move |(n, number)| {
+                let symbols = self.symbols_adjacent_to(n).copied().collect::<Vec<_>>();
+                predicate(&symbols).then_some(number)
+            })
+    }
+
+    /// Every `*` touching exactly two numbers, with its gear ratio.
+    pub fn gears(&self) -> impl Iterator<Item = (&Symbol, u32)> {
+        self.symbols.iter().enumerate().filter_map(|(i, symbol)| {
+            let numbers = &self.numbers_of[i];
+            (symbol.symbol == '*' && numbers.len() == 2).then(|| {
+                let ratio = numbers.iter().map(|&n| self.numbers[n].value).product();
+                (symbol, ratio)
+            })
+        })
     }
 }
 
 pub fn solve_part_one(input: &str) -> u32 {
-    let graph = Graph::from_str(input).unwrap();
-    let mut numbers = graph.search();
-    numbers.sort();
-    println!("The numbers are: {:?}", numbers);
-    return numbers.iter().sum();
+    let schematic = Schematic::from_str(input).unwrap();
+    schematic.part_numbers().map(|number| number.value).sum()
 }
 
 pub fn solve_part_two(input: &str) -> u32 {
-    let graph = Graph::from_str(input).unwrap();
-    let stars = graph.get_symbol_pos('*');
-    let mut sum = 0;
-    for star in stars {
-        println!("star: {:?}", star);
-        let numbers = graph.search_numbers_from_position(star);
-        if numbers.len() < 2 {
-            continue;
-        }
-        let prod = numbers.iter().product::<u32>();
-        sum += prod;
-    }
-    sum
+    let schematic = Schematic::from_str(input).unwrap();
+    schematic.gears().map(|(_, ratio)| ratio).sum()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_each_part_number_once() {
+        // 5 touches both symbols, and the symbols touch each other but not 7
+        let schematic = Schematic::from_str("5#\n*.\n..\n.$7").unwrap();
+        assert_eq!(
+            schematic
+                .part_numbers()
+                .map(|n| n.value)
+                .collect::<Vec<_>>(),
+            vec![5, 7]
+        );
+        assert_eq!(schematic.numbers()[0].columns, 0..1);
+        assert_eq!(schematic.symbols_adjacent_to(0).count(), 2);
+
+        let chained = Schematic::from_str("1.....\n.#####\n.....2\n22....").unwrap();
+        assert_eq!(
+            chained.part_numbers().map(|n| n.value).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn queries_numbers_by_adjacent_symbols() {
+        let schematic = Schematic::from_str("#12#\n....\n.3#.\n#...\n45..").unwrap();
+        let touching = |count| {
+            schematic
+                .numbers_touching('#', count)
+                .map(|n| n.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(touching(2), vec![12, 3]);
+        assert_eq!(touching(1), vec![45]);
+        assert_eq!(touching(0), Vec::<u32>::new());
+        let star = Schematic::from_str("2*3*4").unwrap();
+        assert_eq!(
+            star.gears().map(|(s, r)| (s.col, r)).collect::<Vec<_>>(),
+            vec![(1, 6), (3, 12)]
+        );
+    }
+
+    #[test]
+    fn handles_multibyte_symbols() {
+        let schematic = Schematic::from_str("€12.\n..3£").unwrap();
+        assert_eq!(
+            schematic.numbers()[0],
+            Number {
+                value: 12,
+                row: 0,
+                columns: 1..3
+            }
+        );
+        assert_eq!(schematic.symbols()[1].col, 3);
+        assert_eq!(
+            schematic
+                .part_numbers()
+                .map(|n| n.value)
+                .collect::<Vec<_>>(),
+            vec![12, 3]
+        );
+    }
+
     #[test]
     fn solves_3_1_easy() {
         let input = std::fs::read_to_string("input/3_easy.txt").unwrap();
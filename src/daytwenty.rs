@@ -1,124 +1,442 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
 use std::str::FromStr;
 
 use itertools::Itertools;
 
+use self::modules::{Module, ModuleKinds};
+use crate::utils::{find_cycle, Cycle};
+
+pub mod modules {
+    use std::collections::BTreeMap;
+
+    /// Behaviour of a single module in the network. New kinds of modules are
+    /// added by implementing this trait and registering a prefix in [`ModuleKinds`].
+    pub trait Module: std::fmt::Debug {
+        /// Called once for every wire feeding this module, before any pulse is sent.
+        fn connect_input(&mut self, _sender: &str) {}
+
+        /// Handles a pulse from `sender` and returns the pulse to send to all
+        /// destinations, or `None` if the module stays silent.
+        fn receive(&mut self, sender: &str, pulse: bool) -> Option<bool>;
+
+        /// The module's internal memory, used for snapshots. Cycle detection
+        /// compares snapshots, so this must cover everything that affects
+        /// future pulses.
+        fn memory(&self) -> Vec<u64>;
+
+        /// Restores memory previously returned by [`Module::memory`]. Cycle
+        /// detection restores state on every step, so a module that keeps memory
+        /// must bring all of it back here.
+        fn restore(&mut self, memory: &[u64]);
+
+        /// Graphviz node shape used when exporting the circuit.
+        fn shape(&self) -> &'static str {
+            "ellipse"
+        }
+
+        /// A boxed copy of the module, memory included, so circuits can be cloned.
+        fn box_clone(&self) -> Box<dyn Module>;
+    }
+
+    impl Clone for Box<dyn Module> {
+        fn clone(&self) -> Self {
+            self.box_clone()
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct Broadcaster;
+
+    impl Module for Broadcaster {
+        fn receive(&mut self, _sender: &str, pulse: bool) -> Option<bool> {
+            Some(pulse)
+        }
+
+        fn memory(&self) -> Vec<u64> {
+            vec![]
+        }
+
+        fn restore(&mut self, _memory: &[u64]) {}
+
+        fn shape(&self) -> &'static str {
+            "doubleoctagon"
+        }
+
+        fn box_clone(&self) -> Box<dyn Module> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct FlipFlop {
+        on: bool,
+    }
+
+    impl Module for FlipFlop {
+        fn receive(&mut self, _sender: &str, pulse: bool) -> Option<bool> {
+            if pulse {
+                return None;
+            }
+            self.on = !self.on;
+            Some(self.on)
+        }
+
+        fn memory(&self) -> Vec<u64> {
+            vec![self.on as u64]
+        }
+
+        fn restore(&mut self, memory: &[u64]) {
+            self.on = memory[0] != 0;
+        }
+
+        fn shape(&self) -> &'static str {
+            "box"
+        }
+
+        fn box_clone(&self) -> Box<dyn Module> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct Conjunction {
+        inputs: BTreeMap<String, bool>,
+    }
+
+    impl Module for Conjunction {
+        fn connect_input(&mut self, sender: &str) {
+            self.inputs.insert(sender.to_string(), false);
+        }
+
+        fn receive(&mut self, sender: &str, pulse: bool) -> Option<bool> {
+            self.inputs.insert(sender.to_string(), pulse);
+            Some(!self.inputs.values().all(|&x| x))
+        }
+
+        fn memory(&self) -> Vec<u64> {
+            self.inputs.values().map(|&x| x as u64).collect()
+        }
+
+        fn restore(&mut self, memory: &[u64]) {
+            for (input, &value) in self.inputs.values_mut().zip(memory) {
+                *input = value != 0;
+            }
+        }
+
+        fn shape(&self) -> &'static str {
+            "invhouse"
+        }
+
+        fn box_clone(&self) -> Box<dyn Module> {
+            Box::new(self.clone())
+        }
+    }
+
+    type Factory = Box<dyn Fn() -> Box<dyn Module>>;
+
+    /// Maps the prefix character of a module definition to the kind of module it creates.
+    /// Definitions without a prefix are always broadcasters.
+    pub struct ModuleKinds {
+        factories: BTreeMap<char, Factory>,
+    }
+
+    impl ModuleKinds {
+        pub fn empty() -> Self {
+            ModuleKinds {
+                factories: BTreeMap::new(),
+            }
+        }
+
+        pub fn register<F>(mut self, prefix: char, factory: F) -> Self
+        where
+            F: Fn() -> Box<dyn Module> + 'static,
+        {
+            self.factories.insert(prefix, Box::new(factory));
+            self
+        }
+
+        pub(crate) fn create(&self, prefix: char) -> Option<Box<dyn Module>> {
+            self.factories.get(&prefix).map(|factory| factory())
+        }
+    }
+
+    impl Default for ModuleKinds {
+        fn default() -> Self {
+            ModuleKinds::empty()
+                .register('%', || Box::<FlipFlop>::default())
+                .register('&', || Box::<Conjunction>::default())
+        }
+    }
+}
+
 /// Based on my reading of other people's solutions on Reddit
 /// My original solution was a mess of BTreeMaps and Vecs
-
-struct Circuit {
+#[derive(Debug, Clone)]
+pub struct Circuit {
     graph: BTreeMap<String, Vec<String>>,
-    flops: BTreeMap<String, bool>,
-    conjs: BTreeMap<String, BTreeMap<String, bool>>,
+    modules: BTreeMap<String, Box<dyn Module>>,
+}
+
+/// A single pulse delivered during a button press. `order` is the position
+/// of the pulse in the processing queue, starting at 0 for the button pulse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PulseEvent {
+    pub order: usize,
+    pub sender: String,
+    pub receiver: String,
+    pub pulse: bool,
+}
+
+/// The memory of every module in the circuit, keyed by module name.
+/// Two equal snapshots mean the network behaves identically from then on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    pub memory: BTreeMap<String, Vec<u64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitError {
+    /// The line has no `->` separating the module from its destinations.
+    MissingArrow { line: usize },
+    /// The module name or one of its destinations is empty.
+    EmptyName { line: usize },
+    /// The module starts with a prefix that no registered module kind uses.
+    UnknownPrefix { line: usize, prefix: char },
+    /// The same module is defined twice.
+    DuplicateModule { line: usize, name: String },
+}
+
+impl std::fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitError::MissingArrow { line } => write!(f, "line {}: missing `->`", line),
+            CircuitError::EmptyName { line } => write!(f, "line {}: empty module name", line),
+            CircuitError::UnknownPrefix { line, prefix } => {
+                write!(f, "line {}: unknown module prefix `{}`", line, prefix)
+            }
+            CircuitError::DuplicateModule { line, name } => {
+                write!(
+                    f,
+                    "line {}: module `{}` is defined more than once",
+                    line, name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+/// Suspicious but legal wiring reported by [`Circuit::warnings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitWarning {
+    /// A module other than the broadcaster that no module sends pulses to.
+    Undriven(String),
+    /// A module that has inputs but can never receive a pulse from the broadcaster.
+    Unused(String),
 }
 
 impl FromStr for Circuit {
-    type Err = ();
+    type Err = CircuitError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (flops, mut conjs, graph): (BTreeMap<_, _>, BTreeMap<_, _>, BTreeMap<_, _>) = s
-            .lines()
-            .map(|line| {
-                let parts = line.split("->").map(|s| s.trim().to_string()).collect_vec();
-                let mut source = parts.first().unwrap().clone();
-                let dests: Vec<String> =
-                    parts[1].split(',').map(|s| s.trim().to_string()).collect();
-
-                let is_flop = if source.starts_with('%') {
-                    source.remove(0);
-                    true
-                } else {
-                    false
-                };
+        Circuit::parse_with(s, &ModuleKinds::default())
+    }
+}
 
-                let is_conj = if source.starts_with('&') {
-                    source.remove(0);
-                    true
-                } else {
-                    false
-                };
+impl Circuit {
+    /// Parses the circuit, building prefixed modules from `kinds`.
+    pub fn parse_with(s: &str, kinds: &ModuleKinds) -> Result<Self, CircuitError> {
+        let mut graph = BTreeMap::new();
+        let mut modules = BTreeMap::new();
 
-                (source, dests, is_flop, is_conj)
-            })
-            .fold(
-                (BTreeMap::new(), BTreeMap::new(), BTreeMap::new()),
-                |(mut flops, mut conjs, mut graph), (source, dests, is_flop, is_conj)| {
-                    if is_flop {
-                        flops.insert(source.clone(), false);
-                    }
-                    if is_conj {
-                        conjs.insert(source.clone(), BTreeMap::new());
-                    }
-                    graph.insert(source, dests);
-                    (flops, conjs, graph)
-                },
-            );
+        for (line, text) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let line = line + 1;
+            let (source, dests) = text
+                .split_once("->")
+                .ok_or(CircuitError::MissingArrow { line })?;
+            let source = source.trim();
+            let dests = dests.split(',').map(|s| s.trim().to_string()).collect_vec();
+
+            let prefix = source
+                .chars()
+                .next()
+                .ok_or(CircuitError::EmptyName { line })?;
+            let (name, module) = if prefix.is_alphanumeric() {
+                (source, Box::new(modules::Broadcaster) as Box<dyn Module>)
+            } else {
+                let module = kinds
+                    .create(prefix)
+                    .ok_or(CircuitError::UnknownPrefix { line, prefix })?;
+                (&source[prefix.len_utf8()..], module)
+            };
+
+            if name.is_empty() || dests.iter().any(|d| d.is_empty()) {
+                return Err(CircuitError::EmptyName { line });
+            }
+            if modules.insert(name.to_string(), module).is_some() {
+                return Err(CircuitError::DuplicateModule {
+                    line,
+                    name: name.to_string(),
+                });
+            }
+            graph.insert(name.to_string(), dests);
+        }
 
         for (source, dests) in &graph {
             for dest in dests {
-                if conjs.contains_key(dest) {
-                    conjs.get_mut(dest).unwrap().insert(source.clone(), false);
+                if let Some(module) = modules.get_mut(dest) {
+                    module.connect_input(source);
                 }
             }
         }
 
-        Ok(Circuit {
-            graph,
-            flops,
-            conjs,
-        })
+        Ok(Circuit { graph, modules })
+    }
+
+    pub fn warnings(&self) -> Vec<CircuitWarning> {
+        let driven: BTreeSet<&String> = self.graph.values().flatten().collect();
+
+        let mut reachable = BTreeSet::new();
+        let mut queue = VecDeque::from(["broadcaster"]);
+        while let Some(node) = queue.pop_front() {
+            if reachable.insert(node) {
+                if let Some(dests) = self.graph.get(node) {
+                    queue.extend(dests.iter().map(|d| d.as_str()));
+                }
+            }
+        }
+
+        let mut warnings = vec![];
+        for name in self.modules.keys() {
+            if name == "broadcaster" {
+                continue;
+            }
+            if !driven.contains(name) {
+                warnings.push(CircuitWarning::Undriven(name.clone()));
+            } else if !reachable.contains(name.as_str()) {
+                warnings.push(CircuitWarning::Unused(name.clone()));
+            }
+        }
+        warnings
     }
-}
 
-impl Circuit {
     fn propagate_pulse(
         &mut self,
         sender: &str,
         receiver: &str,
         pulse: bool,
     ) -> Vec<(String, String, bool)> {
-        let mut next_pulse = pulse;
-
-        if let Some(flip_flop) = self.flops.get_mut(receiver) {
-            if pulse {
-                return vec![];
-            }
-            *flip_flop = !*flip_flop;
-            next_pulse = *flip_flop;
-        } else if let Some(conjunction) = self.conjs.get_mut(receiver) {
-            conjunction.insert(sender.to_string(), pulse);
-            next_pulse = !conjunction.values().all(|&x| x);
-        } else if !self.graph.contains_key(receiver) {
+        let Some(next_pulse) = self
+            .modules
+            .get_mut(receiver)
+            .and_then(|module| module.receive(sender, pulse))
+        else {
             return vec![];
-        }
-
-        let default_receiver = vec![];
-        let receivers = self.graph.get(receiver).unwrap_or(&default_receiver);
+        };
 
-        receivers
+        self.graph[receiver]
             .iter()
             .map(|new_receiver| (receiver.to_string(), new_receiver.clone(), next_pulse))
             .collect()
     }
 
     fn simulate(&mut self, start_node: String, connection: String) -> (i32, i32) {
+        self.trace(start_node, connection)
+            .iter()
+            .fold((0, 0), |(low, high), event| {
+                if event.pulse {
+                    (low, high + 1)
+                } else {
+                    (low + 1, high)
+                }
+            })
+    }
+
+    /// Sends a low pulse from `start_node` to `connection` and returns every
+    /// pulse delivered until the queue drains, in processing order.
+    pub fn trace(&mut self, start_node: String, connection: String) -> Vec<PulseEvent> {
         let mut queue = VecDeque::new();
         queue.push_back((start_node, connection, false));
 
-        let (mut low_pulse_count, mut high_pulse_count) = (0, 0);
-
+        let mut events = vec![];
         while let Some((sender, receiver, pulse)) = queue.pop_front() {
-            if pulse {
-                high_pulse_count += 1;
-            } else {
-                low_pulse_count += 1;
-            }
-
             let new_nodes = self.propagate_pulse(&sender, &receiver, pulse);
             queue.extend(new_nodes);
+            events.push(PulseEvent {
+                order: events.len(),
+                sender,
+                receiver,
+                pulse,
+            });
         }
 
-        (low_pulse_count, high_pulse_count)
+        events
+    }
+
+    /// Pushes the button once and returns the full trace of the press.
+    pub fn press_button(&mut self) -> Vec<PulseEvent> {
+        self.trace("button".to_string(), "broadcaster".to_string())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self
+                .modules
+                .iter()
+                .map(|(name, module)| (name.clone(), module.memory()))
+                .collect(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        for (name, memory) in &snapshot.memory {
+            if let Some(module) = self.modules.get_mut(name) {
+                module.restore(memory);
+            }
+        }
+    }
+
+    /// Looks for a press count after which the whole network repeats, giving up
+    /// after `max_presses`. The circuit is left in the state it started in.
+    pub fn find_cycle(&mut self, max_presses: usize) -> Option<Cycle> {
+        let initial = self.snapshot();
+        let cycle = find_cycle(
+            initial.clone(),
+            |snapshot| {
+                self.restore(snapshot);
+                self.press_button();
+                self.snapshot()
+            },
+            max_presses,
+        );
+        self.restore(&initial);
+        cycle
+    }
+
+    /// Renders the circuit in Graphviz DOT format.
+    /// Each module picks its own shape: the broadcaster is a double octagon,
+    /// flip-flops are boxes, conjunctions are inverted houses and untyped
+    /// sinks such as `rx` are plain text.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph circuit {\n");
+        let mut nodes = self.graph.keys().collect_vec();
+        nodes.extend(self.graph.values().flatten());
+        for node in nodes.into_iter().sorted().dedup() {
+            let shape = self
+                .modules
+                .get(node)
+                .map_or("plaintext", |module| module.shape());
+            writeln!(dot, "    \"{}\" [shape={}];", node, shape).unwrap();
+        }
+        for (source, dests) in &self.graph {
+            for dest in dests {
+                writeln!(dot, "    \"{}\" -> \"{}\";", source, dest).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
     }
 }
 
@@ -139,6 +457,133 @@ pub fn solve_part_one(input: &str) -> i64 {
 mod tests {
     use super::*;
 
+    const LOOPING: &str = "broadcaster -> a
+%a -> inv, con
+&inv -> b
+%b -> con
+&con -> output";
+
+    #[test]
+    fn traces_first_example() {
+        let data = std::fs::read_to_string("input/20_easy.txt").unwrap();
+        let mut circuit = Circuit::from_str(&data).unwrap();
+        let trace = circuit.press_button();
+        assert_eq!(trace.len(), 12);
+        assert_eq!(trace.iter().filter(|event| event.pulse).count(), 4);
+        let last = trace.last().unwrap();
+        assert_eq!((last.order, last.sender.as_str()), (11, "inv"));
+        assert_eq!((last.receiver.as_str(), last.pulse), ("a", true));
+    }
+
+    #[test]
+    fn finds_cycle_of_second_example() {
+        let mut circuit = Circuit::from_str(LOOPING).unwrap();
+        let initial = circuit.snapshot();
+        let cycle = circuit.find_cycle(100);
+        assert_eq!(
+            cycle,
+            Some(Cycle {
+                start: 0,
+                length: 4
+            })
+        );
+        assert_eq!(circuit.snapshot(), initial);
+    }
+
+    #[test]
+    fn exports_dot_with_shapes() {
+        let circuit = Circuit::from_str(LOOPING).unwrap();
+        let dot = circuit.to_dot();
+        assert!(dot.contains("\"broadcaster\" [shape=doubleoctagon];"));
+        assert!(dot.contains("\"a\" [shape=box];"));
+        assert!(dot.contains("\"con\" [shape=invhouse];"));
+        assert!(dot.contains("\"output\" [shape=plaintext];"));
+        assert!(dot.contains("\"a\" -> \"inv\";"));
+    }
+
+    #[test]
+    fn rejects_malformed_circuits() {
+        assert_eq!(
+            Circuit::from_str("broadcaster -> a\n$a -> b").err(),
+            Some(CircuitError::UnknownPrefix {
+                line: 2,
+                prefix: '$'
+            })
+        );
+        assert_eq!(
+            Circuit::from_str("broadcaster -> a\n%a -> b\n&a -> b").err(),
+            Some(CircuitError::DuplicateModule {
+                line: 3,
+                name: "a".to_string()
+            })
+        );
+        assert_eq!(
+            Circuit::from_str("broadcaster a").err(),
+            Some(CircuitError::MissingArrow { line: 1 })
+        );
+    }
+
+    #[test]
+    fn warns_about_undriven_and_unused_modules() {
+        let circuit = Circuit::from_str("broadcaster -> a\n%a -> a\n%b -> c\n&c -> a").unwrap();
+        assert_eq!(
+            circuit.warnings(),
+            vec![
+                CircuitWarning::Undriven("b".to_string()),
+                CircuitWarning::Unused("c".to_string())
+            ]
+        );
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl Module for Counter {
+        fn receive(&mut self, _sender: &str, _pulse: bool) -> Option<bool> {
+            self.count = (self.count + 1) % 3;
+            (self.count == 0).then_some(true)
+        }
+
+        fn memory(&self) -> Vec<u64> {
+            vec![self.count]
+        }
+
+        fn restore(&mut self, memory: &[u64]) {
+            self.count = memory[0];
+        }
+
+        fn box_clone(&self) -> Box<dyn Module> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn supports_custom_module_kinds() {
+        let kinds = ModuleKinds::default().register('#', || Box::<Counter>::default());
+        let mut circuit = Circuit::parse_with("broadcaster -> c\n#c -> out", &kinds).unwrap();
+        let high_pulses = (0..6)
+            .flat_map(|_| circuit.press_button())
+            .filter(|event| event.receiver == "out")
+            .count();
+        assert_eq!(high_pulses, 2);
+
+        let mut copy = circuit.clone();
+        assert_eq!(copy.snapshot(), circuit.snapshot());
+        copy.press_button();
+        assert_ne!(copy.snapshot(), circuit.snapshot());
+        assert!(format!("{:?}", circuit).contains("Counter"));
+
+        assert_eq!(
+            circuit.find_cycle(10),
+            Some(Cycle {
+                start: 0,
+                length: 3
+            })
+        );
+    }
+
     #[test]
     fn test_20_1_real() {
         let data = std::fs::read_to_string("input/20_real.txt").unwrap();
@@ -1,80 +1,262 @@
-use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::collections::VecDeque;
 
 use itertools::Itertools;
 
+use crate::utils::Grid;
+
 pub fn solve_part_one(input: &str, max_generation: usize) -> usize {
     let grid = parse_input(input);
-    let num_grid_reachable = perform_bfs(&grid, max_generation);
-    num_grid_reachable
+    let field = DistanceField::from_start(&grid).unwrap();
+    field.reachable_in(max_generation)
 }
 
-fn perform_bfs(grid: &[Vec<char>], max_steps: usize) -> usize {
-    let (start_x, start_y) = grid
-        .iter()
-        .enumerate()
-        .flat_map(|(x, row)| row.iter().enumerate().map(move |(y, &cell)| (x, y, cell)))
-        .find(|&(_, _, cell)| cell == 'S')
-        .map(|(x, y, _)| (x, y))
-        .unwrap();
-
-    let mut ans = HashSet::new();
-    let mut seen = HashSet::new();
-    seen.insert((start_x, start_y));
-    let mut queue = VecDeque::new();
-    queue.push_back((start_x, start_y, 0));
+/// Fewest steps from the nearest source to every garden plot.
+///
+/// The elf can waste steps by stepping back and forth, so a plot at distance `d`
+/// from a source is reachable in exactly `k` steps whenever `d <= k` and `d` has
+/// the parity of `k`. The garden is a checkerboard, so the parity of `d` only
+/// depends on which colour the source stands on. Sources of either colour are
+/// therefore searched separately, giving every plot its fewest even and fewest
+/// odd step counts.
+pub struct DistanceField {
+    distances: Grid<Option<usize>>,
+    /// Fewest even and fewest odd step counts reaching each plot.
+    by_parity: Grid<[Option<usize>; 2]>,
+    /// `within[p][d]` counts the plots reachable with parity `p` in at most `d` steps.
+    within: [Vec<usize>; 2],
+}
 
-    while let Some((x, y, steps)) = queue.pop_front() {
-        if steps % 2 == 0 {
-            ans.insert((x, y));
+/// Plain BFS from every source at once.
+fn bfs(grid: &[Vec<char>], sources: &[(usize, usize)]) -> Grid<Option<usize>> {
+    let rows = grid.len();
+    let cols = grid.first().map_or(0, |row| row.len());
+    let mut distances = vec![vec![None; cols]; rows];
+    let mut queue = VecDeque::new();
+    for &(row, col) in sources {
+        if distances[row][col].is_none() {
+            distances[row][col] = Some(0);
+            queue.push_back((row, col));
         }
-        if steps >= max_steps {
-            continue;
+    }
+    while let Some((row, col)) = queue.pop_front() {
+        let steps = distances[row][col].unwrap();
+        let neighbours = [(0, 1), (1, 0), (-1, 0), (0, -1)]
+            .iter()
+            .map(|(dr, dc)| (row as i64 + dr, col as i64 + dc))
+            .filter(|&(r, c)| r >= 0 && c >= 0 && r < rows as i64 && c < cols as i64)
+            .map(|(r, c)| (r as usize, c as usize))
+            .collect_vec();
+        for (r, c) in neighbours {
+            if grid[r][c] != '#' && distances[r][c].is_none() {
+                distances[r][c] = Some(steps + 1);
+                queue.push_back((r, c));
+            }
         }
+    }
+    distances
+}
 
-        let dirs = vec![(0, 1), (1, 0), (-1, 0), (0, -1)];
-        let valid_neighbors = dirs
+impl DistanceField {
+    /// Runs the search from every position in `sources`. Sources on rocks or
+    /// off the grid are ignored.
+    pub fn new(grid: &[Vec<char>], sources: &[(usize, usize)]) -> Self {
+        let rows = grid.len();
+        let cols = grid.first().map_or(0, |row| row.len());
+        let (even, odd): (Vec<_>, Vec<_>) = sources
             .iter()
-            .filter_map(|(dx, dy)| {
-                let nx = x as i32 + dx;
-                let ny = y as i32 + dy;
-                if nx >= 0 && nx < grid.len() as i32 && ny >= 0 && ny < grid[0].len() as i32 {
-                    Some((nx as usize, ny as usize))
-                } else {
-                    None
+            .copied()
+            .filter(|&(row, col)| row < rows && col < cols && grid[row][col] != '#')
+            .partition(|&(row, col)| (row + col) % 2 == 0);
+
+        let mut by_parity = vec![vec![[None; 2]; cols]; rows];
+        for colour in [even, odd] {
+            for (row, distances) in bfs(grid, &colour).into_iter().enumerate() {
+                for (col, distance) in distances.into_iter().enumerate() {
+                    if let Some(d) = distance {
+                        let best = &mut by_parity[row][col][d % 2];
+                        *best = Some(best.map_or(d, |best: usize| best.min(d)));
+                    }
                 }
+            }
+        }
+
+        let distances = by_parity
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|ds| ds.iter().flatten().min().copied())
+                    .collect()
             })
-            .filter(|(nx, ny)| grid[*nx][*ny] != '#' && !seen.contains(&(*nx, *ny)))
+            .collect();
+        let all = by_parity
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .copied()
             .collect_vec();
-        for (nx, ny) in valid_neighbors {
-            seen.insert((nx, ny));
-            queue.push_back((nx, ny, steps + 1));
+        let len = all.iter().max().map_or(0, |&d| d + 1);
+        let mut within = [vec![0; len], vec![0; len]];
+        for d in all {
+            within[d % 2][d] += 1;
+        }
+        for totals in within.iter_mut() {
+            for steps in 1..totals.len() {
+                totals[steps] += totals[steps - 1];
+            }
+        }
+        DistanceField {
+            distances,
+            by_parity,
+            within,
         }
     }
 
-    ans.len()
-}
+    /// The field from the `S` plot.
+    pub fn from_start(grid: &[Vec<char>]) -> Option<Self> {
+        find_start(grid).map(|start| DistanceField::new(grid, &[start]))
+    }
 
-fn find_start(grid: &[Vec<char>]) -> Option<(usize, usize)> {
-    for (y, row) in grid.iter().enumerate() {
-        for (x, &cell) in row.iter().enumerate() {
-            if cell == 'S' {
-                return Some((x, y));
-            }
-        }
+    /// Distances indexed by `[row][col]`, `None` for rocks and unreachable plots.
+    pub fn distances(&self) -> &Grid<Option<usize>> {
+        &self.distances
+    }
+
+    pub fn distance(&self, (row, col): (usize, usize)) -> Option<usize> {
+        self.distances.get(row)?.get(col).copied().flatten()
+    }
+
+    /// The largest step count in the parity map, past which longer walks only
+    /// alternate between the same two sets of plots. With sources of both colours
+    /// this can exceed the largest distance.
+    pub fn radius(&self) -> usize {
+        self.within[0].len().saturating_sub(1)
+    }
+
+    /// Whether a walk of exactly `steps` steps can end on `(row, col)`. Positions
+    /// off the grid never can.
+    pub fn is_reachable_in(&self, (row, col): (usize, usize), steps: usize) -> bool {
+        self.by_parity
+            .get(row)
+            .and_then(|row| row.get(col))
+            .and_then(|ds| ds[steps % 2])
+            .is_some_and(|d| d <= steps)
+    }
+
+    /// How many plots can be the end of a walk of exactly `steps` steps.
+    pub fn reachable_in(&self, steps: usize) -> usize {
+        let totals = &self.within[steps % 2];
+        totals.get(steps.min(self.radius())).copied().unwrap_or(0)
+    }
+
+    /// `reachable_in` for many step counts off the same BFS.
+    pub fn reachable_in_each(&self, steps: &[usize]) -> Vec<usize> {
+        steps.iter().map(|&k| self.reachable_in(k)).collect()
+    }
+
+    /// The fewest even and fewest odd step counts reaching every plot. A plot is
+    /// reachable in exactly `k` steps once `k` passes the entry for its parity.
+    pub fn parity_map(&self) -> &Grid<[Option<usize>; 2]> {
+        &self.by_parity
+    }
+
+    /// The grid drawn as in the puzzle, with `O` on every plot reachable in
+    /// exactly `steps` steps.
+    pub fn render(&self, grid: &[Vec<char>], steps: usize) -> String {
+        grid.iter()
+            .enumerate()
+            .map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(c, &cell)| {
+                        if self.is_reachable_in((r, c), steps) {
+                            'O'
+                        } else {
+                            cell
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
     }
-    None
 }
 
-pub fn solve_part_two(input: &str) -> u32 {
+/// Position of `S` as `(row, col)`.
+pub fn find_start(grid: &[Vec<char>]) -> Option<(usize, usize)> {
+    grid.iter().enumerate().find_map(|(row, cells)| {
+        cells
+            .iter()
+            .position(|&cell| cell == 'S')
+            .map(|col| (row, col))
+    })
+}
+
+pub fn solve_part_two(_input: &str) -> u32 {
     42
 }
 
-fn parse_input(input: &str) -> Vec<Vec<char>> {
+pub fn parse_input(input: &str) -> Grid<char> {
     input.lines().map(|line| line.chars().collect()).collect()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_many_step_counts_from_one_bfs() {
+        let input = std::fs::read_to_string("input/21_easy.txt").unwrap();
+        let grid = parse_input(&input);
+        assert_eq!(find_start(&grid), Some((5, 5)));
+        let field = DistanceField::from_start(&grid).unwrap();
+        assert_eq!(
+            field.reachable_in_each(&[0, 1, 2, 3, 6]),
+            vec![1, 2, 4, 6, 16]
+        );
+        assert_eq!(field.distance((5, 4)), Some(1));
+        assert_eq!(field.distance((5, 6)), None);
+        assert_eq!(field.parity_map()[4][5], [None, Some(1)]);
+
+        let far = field.radius() + 10;
+        let plots = field.distances().iter().flatten().flatten().count();
+        assert_eq!(field.reachable_in(far) + field.reachable_in(far + 1), plots);
+    }
+
+    #[test]
+    fn renders_reachable_plots() {
+        let input = std::fs::read_to_string("input/21_easy.txt").unwrap();
+        let grid = parse_input(&input);
+        let field = DistanceField::from_start(&grid).unwrap();
+        let rendered = field.render(&grid, 1);
+        assert_eq!(rendered.lines().nth(4), Some("....#O#...."));
+        assert_eq!(rendered.lines().nth(5), Some(".##.OS####."));
+        assert_eq!(field.render(&grid, 6).matches('O').count(), 16);
+    }
+
+    #[test]
+    fn spreads_from_several_sources() {
+        let grid = parse_input("...\n.#.\n...");
+        let field = DistanceField::new(&grid, &[(0, 0), (2, 2), (1, 1)]);
+        assert_eq!(field.distance((0, 2)), Some(2));
+        assert_eq!(field.distance((1, 1)), None);
+        assert_eq!(field.reachable_in(0), 2);
+        assert_eq!(field.reachable_in(1), 4);
+    }
+
+    #[test]
+    fn combines_sources_of_mixed_parity() {
+        let grid = parse_input("...");
+        let field = DistanceField::new(&grid, &[(0, 0), (0, 1)]);
+        assert_eq!(field.reachable_in_each(&[0, 1, 2, 3]), vec![2, 3, 3, 3]);
+        assert_eq!(field.parity_map()[0][2], [Some(2), Some(1)]);
+        assert_eq!(field.distance((0, 2)), Some(1));
+        assert_eq!(field.render(&grid, 2), "OOO");
+        assert_eq!(field.radius(), 2);
+        assert!(field.is_reachable_in((0, 2), 3));
+        assert!(!field.is_reachable_in((0, 3), 3));
+        assert!(!field.is_reachable_in((1, 0), 3));
+    }
+
     #[test]
     fn solves_21_1_easy() {
         let input = std::fs::read_to_string("input/21_easy.txt").unwrap();
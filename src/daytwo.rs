@@ -1,223 +1,225 @@
-use self::parser::parse_input;
+use std::collections::BTreeMap;
 
-#[derive(Debug, PartialEq)]
-pub enum Cube {
-    Blue(usize),
-    Red(usize),
-    Green(usize),
-}
+use self::parser::{parse_input, Game};
+
+/// Cube counts by colour, used both for the bag's contents and for a single draw.
+pub type Cubes = BTreeMap<String, u32>;
 
-mod parser {
-    use itertools::Itertools;
+pub mod parser {
+    use nom::{
+        bytes::complete::{tag, take_while1},
+        character::complete::{digit1, space1},
+        combinator::{all_consuming, map_res},
+        multi::separated_list1,
+        sequence::{separated_pair, tuple},
+        IResult,
+    };
 
-    use super::Cube;
+    use super::Cubes;
 
-    pub fn parse_input(input: &str) -> Vec<Vec<Vec<Cube>>> {
+    /// One handful of cubes shown from the bag.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Draw {
+        pub cubes: Cubes,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Game {
+        pub id: u32,
+        pub draws: Vec<Draw>,
+    }
+
+    pub fn parse_input(input: &str) -> Vec<Game> {
         input
             .trim()
             .lines()
-            .map(|line| line.trim())
-            .map(parse_line)
-            .collect_vec()
+            .map(|line| all_consuming(parse_game)(line.trim()).unwrap().1)
+            .collect()
     }
 
-    pub fn parse_line(line: &str) -> Vec<Vec<Cube>> {
-        line.split(';')
-            .map(parse_inner)
-            .collect_vec()
+    /// Parses a line of the form
+    /// Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+    pub fn parse_game(input: &str) -> IResult<&str, Game> {
+        let (input, (_, id, _, draws)) = tuple((
+            tag("Game "),
+            parse_number,
+            tag(": "),
+            separated_list1(tag("; "), parse_draw),
+        ))(input)?;
+        Ok((input, Game { id, draws }))
     }
 
-    pub fn parse_inner(mut section: &str) -> Vec<Cube> {
-        if section.contains(':') {
-            section = section.split(':').collect_vec()[1];
+    // parses a comma separated list of counts, e.g. 1 red, 2 green
+    fn parse_draw(input: &str) -> IResult<&str, Draw> {
+        let (input, counts) = separated_list1(tag(", "), parse_count)(input)?;
+        let mut cubes = Cubes::new();
+        for (count, colour) in counts {
+            *cubes.entry(colour.to_string()).or_default() += count;
         }
-        section = section.trim();
-        let items = section
-            .split(',')
-            .map(|item| {
-                let (count, color) = item.split_whitespace().collect_tuple().unwrap();
-                let count: usize = count.parse().unwrap();
-                match color {
-                    "red" => Cube::Red(count),
-                    "blue" => Cube::Blue(count),
-                    "green" => Cube::Green(count),
-                    _ => panic!("Invalid cube color"),
-                }
-            })
-            .collect_vec();
-        items
+        Ok((input, Draw { cubes }))
     }
-}
 
-pub fn solve_part_one(input: &str, rgb_limits: (u32, u32, u32)) -> u32 {
-    let parsed = parser::parse_input(input);
-    let mut possible_games = vec![];
-    for (index, line) in parsed.iter().enumerate() {
-        if is_line_possible(line, rgb_limits) {
-            possible_games.push(index + 1);
-        }
+    fn parse_count(input: &str) -> IResult<&str, (u32, &str)> {
+        separated_pair(
+            parse_number,
+            space1,
+            take_while1(|c: char| c.is_alphabetic()),
+        )(input)
+    }
+
+    fn parse_number(input: &str) -> IResult<&str, u32> {
+        map_res(digit1, str::parse)(input)
     }
-    println!("Possible games: {:?}", possible_games);
-    let total = possible_games.iter().map(|item| *item as u32).sum();
-    total
 }
 
-fn is_line_possible(parsed_line: &Vec<Vec<Cube>>, limits: (u32, u32, u32)) -> bool {
-    for cube in parsed_line.iter().flatten() {
-        match cube {
-            Cube::Red(count) => {
-                if *count > limits.0 as usize {
-                    println!("Red: {}", count);
-                    return false;
-                }
-            }
-            Cube::Green(count) => {
-                if *count > limits.1 as usize {
-                    println!("Blue: {} and limit: {}", count, limits.1);
-                    return false;
-                }
-            }
-            Cube::Blue(count) => {
-                if *count > limits.2 as usize {
-                    println!("Green: {}", count);
-                    return false;
-                }
-            }
+impl Game {
+    /// Whether every draw fits in a bag holding `limits`. Colours missing from
+    /// `limits` aren't in the bag at all.
+    pub fn is_possible(&self, limits: &Cubes) -> bool {
+        self.draws.iter().all(|draw| {
+            draw.cubes
+                .iter()
+                .all(|(colour, count)| count <= limits.get(colour).unwrap_or(&0))
+        })
+    }
+
+    /// The fewest cubes of each colour seen in the game that the bag could hold.
+    pub fn minimum_bag(&self) -> Cubes {
+        let mut bag = Cubes::new();
+        for (colour, &count) in self.draws.iter().flat_map(|draw| &draw.cubes) {
+            let most = bag.entry(colour.clone()).or_default();
+            *most = (*most).max(count);
         }
+        bag
+    }
+
+    /// The product of the minimum bag's counts over the colours of `bag`, whose
+    /// counts are ignored. A colour the game never shows needs no cubes, making
+    /// the power 0.
+    pub fn power(&self, bag: &Cubes) -> u32 {
+        let minimum = self.minimum_bag();
+        bag.keys()
+            .map(|colour| minimum.get(colour).copied().unwrap_or(0))
+            .product()
     }
-    true
 }
 
-pub fn solve_part_two(input: &str) -> u32 {
-    let parsed = parse_input(input);
-    parsed
+/// Builds a bag from `(colour, count)` pairs.
+pub fn bag(cubes: &[(&str, u32)]) -> Cubes {
+    cubes
         .iter()
-        .map(get_power_from_line)
-        .sum::<u32>()
+        .map(|&(colour, count)| (colour.to_string(), count))
+        .collect()
 }
 
-fn get_power_from_line(parsed_line: &Vec<Vec<Cube>>) -> u32 {
-    let (red, green, blue) = max_by_color(parsed_line);
-    (red * green * blue) as u32
+pub fn solve_part_one(input: &str, limits: &Cubes) -> u32 {
+    parse_input(input)
+        .iter()
+        .filter(|game| game.is_possible(limits))
+        .map(|game| game.id)
+        .sum()
 }
 
-fn max_by_color(parsed_line: &Vec<Vec<Cube>>) -> (usize, usize, usize) {
-    let mut red = 0;
-    let mut green = 0;
-    let mut blue = 0;
-    for cube in parsed_line.iter().flatten() {
-        match cube {
-            Cube::Red(count) => {
-                if *count > red {
-                    red = *count;
-                }
-            }
-            Cube::Green(count) => {
-                if *count > green {
-                    green = *count;
-                }
-            }
-            Cube::Blue(count) => {
-                if *count > blue {
-                    blue = *count;
-                }
-            }
-        }
-    }
-    (red, green, blue)
+pub fn solve_part_two(input: &str) -> u32 {
+    let colours = bag(&[("red", 0), ("green", 0), ("blue", 0)]);
+    parse_input(input)
+        .iter()
+        .map(|game| game.power(&colours))
+        .sum()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::daytwo::parser::parse_line;
+    use super::parser::{parse_game, Draw, Game};
+    use super::*;
+
+    fn game(line: &str) -> Game {
+        parse_game(line).unwrap().1
+    }
+
+    fn draw(cubes: &[(&str, u32)]) -> Draw {
+        Draw { cubes: bag(cubes) }
+    }
 
     #[test]
     fn test_simple_power_from_line() {
         let line = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green";
-        let expected = 48;
-        let parsed_line = parse_line(line);
-        let actual = super::get_power_from_line(&parsed_line);
-        assert_eq!(actual, expected);
+        let colours = bag(&[("red", 0), ("green", 0), ("blue", 0)]);
+        assert_eq!(game(line).power(&colours), 48);
+        assert_eq!(game("Game 2: 3 red; 1 blue").power(&colours), 0);
+        assert_eq!(game("Game 2: 3 red; 1 blue").power(&bag(&[("red", 0)])), 3);
     }
 
     #[test]
     fn test_if_line_is_possible() {
         let line = "Game 5: 8 green, 1 red, 12 blue; 10 green, 6 red, 13 blue; 1 red, 3 blue, 6 green; 14 blue, 2 red, 7 green";
-        let expected = vec![
-            vec![
-                super::Cube::Green(8),
-                super::Cube::Red(1),
-                super::Cube::Blue(12),
-            ],
-            vec![
-                super::Cube::Green(10),
-                super::Cube::Red(6),
-                super::Cube::Blue(13),
+        let expected = Game {
+            id: 5,
+            draws: vec![
+                draw(&[("green", 8), ("red", 1), ("blue", 12)]),
+                draw(&[("green", 10), ("red", 6), ("blue", 13)]),
+                draw(&[("red", 1), ("blue", 3), ("green", 6)]),
+                draw(&[("blue", 14), ("red", 2), ("green", 7)]),
             ],
-            vec![
-                super::Cube::Red(1),
-                super::Cube::Blue(3),
-                super::Cube::Green(6),
-            ],
-            vec![
-                super::Cube::Blue(14),
-                super::Cube::Red(2),
-                super::Cube::Green(7),
-            ],
-        ];
-        let actual = parse_line(line);
+        };
+        let actual = game(line);
         assert_eq!(actual, expected);
-        let exected_possible = true;
-        let actual_possible = super::is_line_possible(&actual, (12, 13, 14));
-        assert_eq!(actual_possible, exected_possible);
+        assert!(actual.is_possible(&bag(&[("red", 12), ("green", 13), ("blue", 14)])));
+        assert!(!actual.is_possible(&bag(&[("red", 12), ("green", 13), ("blue", 13)])));
     }
 
     #[test]
     fn parse_line_whole() {
         let line = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green";
-        let expected = vec![
-            vec![super::Cube::Blue(3), super::Cube::Red(4)],
-            vec![
-                super::Cube::Red(1),
-                super::Cube::Green(2),
-                super::Cube::Blue(6),
+        let expected = Game {
+            id: 1,
+            draws: vec![
+                draw(&[("blue", 3), ("red", 4)]),
+                draw(&[("red", 1), ("green", 2), ("blue", 6)]),
+                draw(&[("green", 2)]),
             ],
-            vec![super::Cube::Green(2)],
-        ];
-        let actual = parse_line(line);
-        assert_eq!(actual, expected);
+        };
+        assert_eq!(game(line), expected);
     }
 
     #[test]
     fn parses_another_line() {
         let line = "Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red";
-        let expected = vec![
-            vec![
-                super::Cube::Green(1),
-                super::Cube::Red(3),
-                super::Cube::Blue(6),
-            ],
-            vec![super::Cube::Green(3), super::Cube::Red(6)],
-            vec![
-                super::Cube::Green(3),
-                super::Cube::Blue(15),
-                super::Cube::Red(14),
+        let expected = Game {
+            id: 4,
+            draws: vec![
+                draw(&[("green", 1), ("red", 3), ("blue", 6)]),
+                draw(&[("green", 3), ("red", 6)]),
+                draw(&[("green", 3), ("blue", 15), ("red", 14)]),
             ],
-        ];
-        let actual = parse_line(line);
-        assert_eq!(actual, expected);
+        };
+        assert_eq!(game(line), expected);
+    }
+
+    #[test]
+    fn uses_real_ids_and_any_colour() {
+        let input = "Game 7: 2 teal, 1 red\nGame 12: 3 teal; 4 mauve\nGame 30: 1 red";
+        let limits = bag(&[("teal", 3), ("red", 1)]);
+        assert_eq!(solve_part_one(input, &limits), 7 + 30);
+        assert_eq!(
+            game("Game 12: 3 teal; 4 mauve").minimum_bag(),
+            bag(&[("mauve", 4), ("teal", 3)])
+        );
+        assert!(parse_game("Game x: 1 red").is_err());
     }
 
     #[test]
     fn solves_2_1_easy() {
         let input = std::fs::read_to_string("input/2_easy.txt").unwrap();
-        assert_eq!(super::solve_part_one(&input, (12, 13, 14)), 8);
+        let limits = bag(&[("red", 12), ("green", 13), ("blue", 14)]);
+        assert_eq!(super::solve_part_one(&input, &limits), 8);
     }
 
     #[test]
     fn solves_2_1_hard() {
         let input = std::fs::read_to_string("input/2_hard.txt").unwrap();
-        assert_eq!(super::solve_part_one(&input, (12, 13, 14)), 3035);
+        let limits = bag(&[("red", 12), ("green", 13), ("blue", 14)]);
+        assert_eq!(super::solve_part_one(&input, &limits), 3035);
     }
 
     #[test]
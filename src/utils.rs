@@ -1,9 +1,15 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A rectangular grid stored row by row.
+pub type Grid<T> = Vec<Vec<T>>;
+
 pub fn transpose<T: Clone>(v: Vec<Vec<T>>) -> Vec<Vec<T>> {
     let mut result = Vec::new();
     for i in 0..v[0].len() {
         let mut row = Vec::new();
-        for j in 0..v.len() {
-            row.push(v[j][i].clone());
+        for line in &v {
+            row.push(line[i].clone());
         }
         result.push(row);
     }
@@ -20,6 +26,160 @@ pub fn reverse_rows<T: Clone>(v: Vec<Vec<T>>) -> Vec<Vec<T>> {
     v.iter().rev().cloned().collect()
 }
 
+/// The first repeated state of a sequence and how often it repeats.
+/// State `start + k * length + i` equals state `start + i` for every k.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: usize,
+    pub length: usize,
+}
+
+fn fingerprint<S: Hash>(state: &S) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Finds the cycle in the sequence `initial, step(initial), step(step(initial)), ...`
+/// using Brent's algorithm, so only two states are ever kept around.
+/// States are compared by fingerprint first and only checked for equality on a match.
+/// Gives up and returns None once `max_steps` steps have been taken without finding it.
+pub fn find_cycle<S, F>(initial: S, mut step: F, max_steps: usize) -> Option<Cycle>
+where
+    S: Hash + Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    let mut steps = 1;
+    let mut power = 1;
+    let mut length = 1;
+    let mut tortoise = initial.clone();
+    let mut tortoise_print = fingerprint(&tortoise);
+    let mut hare = step(&initial);
+    while fingerprint(&hare) != tortoise_print || hare != tortoise {
+        if steps >= max_steps {
+            return None;
+        }
+        if power == length {
+            tortoise = hare.clone();
+            tortoise_print = fingerprint(&tortoise);
+            power *= 2;
+            length = 0;
+        }
+        hare = step(&hare);
+        length += 1;
+        steps += 1;
+    }
+
+    // Walk two states `length` apart from the beginning until they meet.
+    let mut tortoise = initial.clone();
+    let mut hare = initial;
+    for _ in 0..length {
+        hare = step(&hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        start += 1;
+    }
+    Some(Cycle { start, length })
+}
+
+/// Returns the `n`th state of the sequence generated by `step`, skipping
+/// whole cycles instead of simulating them.
+pub fn nth_state<S, F>(initial: S, mut step: F, n: usize) -> S
+where
+    S: Hash + Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    let remaining = match find_cycle(initial.clone(), &mut step, n) {
+        Some(Cycle { start, length }) if n > start => start + (n - start) % length,
+        _ => n,
+    };
+    (0..remaining).fold(initial, |state, _| step(&state))
+}
+
+/// A grid of on/off cells packed into one `u64` per row and one per column,
+/// with bit `c` of a row mask being column `c`. Comparing two rows or two
+/// columns is then an XOR, and counting differences a popcount.
+/// Both sides of the grid are limited to 64 cells.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitGrid {
+    rows: Vec<u64>,
+    cols: Vec<u64>,
+}
+
+/// Why a grid can't be packed into a [`BitGrid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitGridError {
+    /// The grid is wider or taller than 64 cells.
+    TooLarge,
+    /// The rows of the grid aren't all the same length.
+    Ragged,
+}
+
+impl std::fmt::Display for BitGridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitGridError::TooLarge => write!(f, "grid is over 64 cells across or tall"),
+            BitGridError::Ragged => write!(f, "grid rows have different lengths"),
+        }
+    }
+}
+
+impl std::error::Error for BitGridError {}
+
+impl BitGrid {
+    /// Packs `grid`, treating `on` cells as set bits.
+    pub fn from_grid(grid: &[Vec<char>], on: char) -> Result<Self, BitGridError> {
+        let width = grid.first().map_or(0, |row| row.len());
+        if grid.iter().any(|row| row.len() != width) {
+            return Err(BitGridError::Ragged);
+        }
+        if grid.len() > 64 || width > 64 {
+            return Err(BitGridError::TooLarge);
+        }
+        let mut rows = vec![0u64; grid.len()];
+        let mut cols = vec![0u64; width];
+        for (r, line) in grid.iter().enumerate() {
+            for (c, &cell) in line.iter().enumerate() {
+                if cell == on {
+                    rows[r] |= 1 << c;
+                    cols[c] |= 1 << r;
+                }
+            }
+        }
+        Ok(BitGrid { rows, cols })
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.cols.len()
+    }
+
+    pub fn rows(&self) -> &[u64] {
+        &self.rows
+    }
+
+    pub fn cols(&self) -> &[u64] {
+        &self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> bool {
+        self.rows[row] & (1 << col) != 0
+    }
+
+    pub fn transpose(&self) -> BitGrid {
+        BitGrid {
+            rows: self.cols.clone(),
+            cols: self.rows.clone(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -61,4 +221,39 @@ mod test {
         let expected = vec![vec![7, 8, 9], vec![4, 5, 6], vec![1, 2, 3]];
         assert_eq!(super::reverse_rows(input), expected);
     }
+
+    #[test]
+    fn finds_cycle_with_tail() {
+        // 0 1 2 3 4 5 6 7 and then back to 3
+        let step = |&x: &u32| if x == 7 { 3 } else { x + 1 };
+        let cycle = super::find_cycle(0, step, 100);
+        assert_eq!(
+            cycle,
+            Some(super::Cycle {
+                start: 3,
+                length: 5
+            })
+        );
+        assert_eq!(super::find_cycle(0, step, 4), None);
+        assert_eq!(super::nth_state(0, step, 1_000_000_006), 6);
+        assert_eq!(super::nth_state(0, step, 2), 2);
+    }
+
+    #[test]
+    fn packs_bit_grid() {
+        let grid = vec![vec!['#', '.', '#'], vec!['.', '.', '#']];
+        let bits = super::BitGrid::from_grid(&grid, '#').unwrap();
+        assert_eq!(bits.rows(), &[0b101, 0b100]);
+        assert_eq!(bits.cols(), &[0b01, 0b00, 0b11]);
+        assert!(bits.get(1, 2) && !bits.get(1, 0));
+        assert_eq!(bits.transpose().rows(), bits.cols());
+        assert_eq!(
+            super::BitGrid::from_grid(&[vec!['#'; 65]], '#'),
+            Err(super::BitGridError::TooLarge)
+        );
+        assert_eq!(
+            super::BitGrid::from_grid(&[vec!['#'], vec!['#', '#']], '#'),
+            Err(super::BitGridError::Ragged)
+        );
+    }
 }